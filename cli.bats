@@ -198,3 +198,298 @@ teardown() {
     [ "$status" -eq 0 ]
     [[ "$output" == *"$address"* ]]
 }
+
+@test "succeeds: audit show && verify" {
+    run $EMERALD_VAULT --chain=morden account new \
+        <<< $'foo\n'
+    [ "$status" -eq 0 ]
+
+    local address=$(echo "$output" | perl -lane 'print $F[-1]' | tr -d '\n')
+    local removeme='!passphrase:'
+    local replacewith=''
+    address="${address//$removeme/$replacewith}"
+    [[ "$address" == *"0x"* ]]
+
+    run $EMERALD_VAULT --chain=morden account strip \
+        "$address" \
+        <<< $'foo\n'
+    [ "$status" -eq 0 ]
+
+    run $EMERALD_VAULT audit show
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"account strip"* ]]
+    [[ "$output" == *"$address"* ]]
+    [[ "$output" != *"Private key"* ]]
+
+    run $EMERALD_VAULT audit verify
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"entries verified: 1"* ]]
+}
+
+@test "succeeds: address add && rename && remove" {
+    run $EMERALD_VAULT --chain=morden address add \
+        0x0e7c045110b8dbf29765047380898919c5cb56f4 \
+        --name=alice
+    [ "$status" -eq 0 ]
+
+    run $EMERALD_VAULT --chain=morden address rename alice bob
+    [ "$status" -eq 0 ]
+
+    run $EMERALD_VAULT --chain=morden address list
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"bob"* ]]
+    [[ "$output" != *"alice"* ]]
+
+    run $EMERALD_VAULT --chain=morden address remove bob
+    [ "$status" -eq 0 ]
+
+    run $EMERALD_VAULT --chain=morden address list
+    [ "$status" -eq 0 ]
+    [[ "$output" != *"0x0e7c045110b8dbf29765047380898919c5cb56f4"* ]]
+}
+
+@test "succeeds: config show with profile" {
+    cat > $EMERALD_BASE_PATH/config.toml <<CONFIG
+default_profile = "dev"
+
+[profiles.dev]
+chain = "morden"
+gas = "0x5208"
+CONFIG
+
+    run $EMERALD_VAULT config show
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"Profile:     dev"* ]]
+    [[ "$output" == *"morden"*"profile"* ]]
+
+    run $EMERALD_VAULT --chain=mainnet config show
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"mainnet"*"cli"* ]]
+    [[ "$output" == *"max_gas_price"*"0xe8d4a51000"*"default"* ]]
+}
+
+@test "succeeds: config show with integer values and command-line arguments" {
+    cat > $EMERALD_BASE_PATH/config.toml <<CONFIG
+default_profile = "dev"
+
+[profiles.dev]
+gas = 21000
+timeout = 30
+retries = 3
+CONFIG
+
+    run $EMERALD_VAULT config show
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"gas "*"21000"*"profile"* ]]
+    [[ "$output" == *"retries"*"3"*"profile"* ]]
+
+    run $EMERALD_VAULT config show --gas=0x5208 --timeout=5 --upstream=127.0.0.1:8546
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"gas "*"0x5208"*"cli"* ]]
+    [[ "$output" == *"timeout "*"5"*"cli"* ]]
+    [[ "$output" == *"upstream"*"127.0.0.1:8546"*"cli"* ]]
+}
+
+@test "fails: unknown config profile" {
+    run $EMERALD_VAULT --profile=nope config show
+    [ "$status" -eq 1 ]
+}
+
+@test "succeeds: account new and list for custom chain" {
+    cat > $EMERALD_BASE_PATH/chains.toml <<CHAINS
+[[chain]]
+name = "dev"
+id = 1337
+CHAINS
+
+    run $EMERALD_VAULT --chain=dev account new <<< $'foo\n'
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"Created new account"* ]]
+
+    run $EMERALD_VAULT --chain=dev account list
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"0x"* ]]
+
+    run $EMERALD_VAULT --chain=mainnet account list
+    [ "$status" -eq 0 ]
+    [[ "$output" != *"0x"* ]]
+}
+
+@test "succeeds: address book for custom chain" {
+    cat > $EMERALD_BASE_PATH/chains.toml <<CHAINS
+[[chain]]
+name = "dev"
+id = 1337
+CHAINS
+
+    run $EMERALD_VAULT --chain=dev address add 0x0e7c045110b8dbf29765047380898919c5cb56f4 --name=alice
+    [ "$status" -eq 0 ]
+    [ -d $EMERALD_BASE_PATH/dev/addressbook ]
+    [ ! -d $EMERALD_BASE_PATH/dev/contracts ]
+
+    run $EMERALD_VAULT --chain=dev address list
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"alice"* ]]
+
+    run $EMERALD_VAULT --chain=dev contract list
+    [ "$status" -eq 0 ]
+}
+
+@test "fails: nonce and transaction send take upstream from EMERALD_NODE" {
+    export EMERALD_NODE=127.0.0.1:1
+
+    run $EMERALD_VAULT --chain=morden nonce 0x0e7c045110b8dbf29765047380898919c5cb56f4
+    [ "$status" -eq 1 ]
+    [[ "$output" != *"required arguments were not provided"* ]]
+
+    run $EMERALD_VAULT --chain=morden transaction send 0xf86c
+    [ "$status" -eq 1 ]
+    [[ "$output" != *"required arguments were not provided"* ]]
+
+    unset EMERALD_NODE
+}
+
+@test "succeeds: nonce reset" {
+    run $EMERALD_VAULT --chain=morden nonce reset 0x0e7c045110b8dbf29765047380898919c5cb56f4
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"Nonces reset for 0x0e7c045110b8dbf29765047380898919c5cb56f4"* ]]
+
+    run $EMERALD_VAULT --chain=morden nonce reset
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"Nonces reset for all addresses"* ]]
+}
+
+@test "fails: transaction new from unknown sender [without panic]" {
+    run $EMERALD_VAULT --chain=morden transaction new \
+        0x0e7c045110b8dbf29765047380898919c5cb56f4 \
+        0x085fb4f24031eaedbc2b611aa528f22343eb52db \
+        0x1 0x5208 0x4a817c800 0x 0x0 --yes
+    [ "$status" -eq 1 ]
+    [[ "$output" != *"panicked"* ]]
+}
+
+@test "succeeds: transaction history [empty]" {
+    run $EMERALD_VAULT --chain=morden transaction history
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"HASH"* ]]
+    [[ "$output" != *"0x"* ]]
+}
+
+@test "succeeds: token add, list and remove" {
+    local addr=0x085fb4f24031eaedbc2b611aa528f22343eb52db
+
+    run $EMERALD_VAULT --chain=morden token add bec $addr --symbol=BEC --decimals=18
+    [ "$status" -eq 0 ]
+    run $EMERALD_VAULT --chain=morden token list
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"bec"*"$addr"*"BEC"* ]]
+
+    run $EMERALD_VAULT --chain=morden token remove bec
+    [ "$status" -eq 0 ]
+    run $EMERALD_VAULT --chain=morden token list
+    [ "$status" -eq 0 ]
+    [[ "$output" != *"$addr"* ]]
+
+    run $EMERALD_VAULT --chain=morden token remove bec
+    [ "$status" -eq 1 ]
+    [[ "$output" == *"Unknown token: bec"* ]]
+}
+
+@test "succeeds: contract import, update, hide and export" {
+    cat > $EMERALD_BASE_PATH/abi.json <<ABI
+[{"type": "function", "name": "totalSupply", "inputs": [], "outputs": [{"type": "uint256"}]}]
+ABI
+    local addr=0x085fb4f24031eaedbc2b611aa528f22343eb52db
+
+    run $EMERALD_VAULT --chain=morden contract import $EMERALD_BASE_PATH/abi.json \
+        --address=$addr --name=BitEther
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"Imported contract: $addr"* ]]
+    [ ! -f $EMERALD_BASE_PATH/morden/contracts/$addr.abi.json ]
+
+    run $EMERALD_VAULT --chain=morden contract update $addr --name=BEC
+    [ "$status" -eq 0 ]
+
+    run $EMERALD_VAULT --chain=morden contract hide $addr
+    [ "$status" -eq 0 ]
+    run $EMERALD_VAULT --chain=morden contract list
+    [ "$status" -eq 0 ]
+    [[ "$output" != *"$addr"* ]]
+
+    run $EMERALD_VAULT --chain=morden contract list --show-hidden
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"BEC"* ]]
+
+    mkdir $EMERALD_BASE_PATH/export
+    run $EMERALD_VAULT --chain=morden contract export $addr $EMERALD_BASE_PATH/export
+    [ "$status" -eq 0 ]
+    grep -q totalSupply $EMERALD_BASE_PATH/export/$addr.json
+}
+
+@test "fails: contract import with invalid ABI" {
+    cat > $EMERALD_BASE_PATH/contract.json <<CONTRACT
+{"address": "0x085fb4f24031eaedbc2b611aa528f22343eb52db", "abi": {"type": "function"}}
+CONTRACT
+
+    run $EMERALD_VAULT --chain=morden contract import $EMERALD_BASE_PATH/contract.json
+    [ "$status" -eq 1 ]
+    [[ "$output" == *"Contract ABI should be an array"* ]]
+}
+
+@test "fails: contract deploy without bytecode file" {
+    run $EMERALD_VAULT contract deploy \
+        --from=0x0e7c045110b8dbf29765047380898919c5cb56f4 \
+        --bytecode=$EMERALD_BASE_PATH/missing.bin
+    [ "$status" -eq 1 ]
+}
+
+@test "succeeds: account check moves broken keyfile to quarantine" {
+    cat > $EMERALD_BASE_PATH/chains.toml <<CHAINS
+[[chain]]
+name = "dev"
+id = 1337
+CHAINS
+    mkdir -p $EMERALD_BASE_PATH/dev/keystore
+    echo '{"version": 3,' > $EMERALD_BASE_PATH/dev/keystore/broken.json
+    echo 'other' > $EMERALD_BASE_PATH/dev/keystore/LOCK
+
+    run $EMERALD_VAULT --chain=dev account check
+    [ "$status" -eq 1 ]
+    [[ "$output" == *"invalid JSON"* ]]
+
+    run $EMERALD_VAULT --chain=dev account check --quarantine
+    [ "$status" -eq 0 ]
+    [ -f $EMERALD_BASE_PATH/dev/quarantine/broken.json ]
+    [ ! -f $EMERALD_BASE_PATH/dev/keystore/broken.json ]
+    [ -f $EMERALD_BASE_PATH/dev/keystore/LOCK ]
+}
+
+@test "succeeds: account check quarantine for built-in chain" {
+    run $EMERALD_VAULT --chain=morden account new <<< $'foo\n'
+    [ "$status" -eq 0 ]
+    echo '{"version": 3,' > $EMERALD_BASE_PATH/morden/keystore/broken.json
+
+    run $EMERALD_VAULT --chain=morden account check
+    [ "$status" -eq 1 ]
+    [[ "$output" == *"invalid JSON"* ]]
+
+    run $EMERALD_VAULT --chain=morden account check --quarantine
+    [ "$status" -eq 0 ]
+    [ -f $EMERALD_BASE_PATH/morden/quarantine/broken.json ]
+
+    run $EMERALD_VAULT --chain=morden account list
+    [ "$status" -eq 0 ]
+}
+
+@test "succeeds: account verify checks passphrase" {
+    run $EMERALD_VAULT --chain=morden account new <<< $'foo\n'
+    [ "$status" -eq 0 ]
+    address=$(echo "$output" | grep -o '0x[0-9a-f]\{40\}')
+
+    run $EMERALD_VAULT --chain=morden account verify $address <<< $'foo\n'
+    [ "$status" -eq 0 ]
+    [[ "$output" == *"Passphrase is valid"* ]]
+
+    run $EMERALD_VAULT --chain=morden account verify $address <<< $'bar\n'
+    [ "$status" -eq 1 ]
+}
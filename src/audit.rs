@@ -0,0 +1,349 @@
+//! # Append-only audit log of key-using operations
+//!
+//! Every entry is a single JSON line, chained to the previous one
+//! through `prev_hash`. Entries never contain secrets, only metadata
+//! about the operation and its outcome. Sequence number and hash of the
+//! last entry are kept in a separate head file, so removed trailing
+//! entries are detected too.
+
+use cmd::{lock_file, write_atomic, Error};
+use emerald::keccak256;
+use hex::ToHex;
+use serde_json;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Audit log filename inside base path
+pub const AUDIT_LOG_FILE: &str = "audit.log";
+
+/// Filename of the last entry reference inside base path
+pub const AUDIT_HEAD_FILE: &str = "audit.head";
+
+/// `prev_hash` value for the very first entry
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Key-using operation to be recorded
+#[derive(Clone, Debug, Default)]
+pub struct Event {
+    pub operation: String,
+    pub chain: String,
+    pub address: Option<String>,
+    pub tx_hash: Option<String>,
+    pub to: Option<String>,
+    pub value: Option<String>,
+}
+
+impl Event {
+    /// Create event for operation on selected chain
+    pub fn new(operation: &str, chain: &str) -> Event {
+        Event {
+            operation: operation.to_string(),
+            chain: chain.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Single record of the audit log
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Entry {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub operation: String,
+    pub chain: String,
+    pub address: Option<String>,
+    pub tx_hash: Option<String>,
+    pub to: Option<String>,
+    pub value: Option<String>,
+    pub outcome: String,
+    pub prev_hash: String,
+    #[serde(default)]
+    pub hash: String,
+}
+
+impl Entry {
+    /// Hash of the entry content, `hash` field itself is excluded
+    pub fn digest(&self) -> String {
+        let mut e = self.clone();
+        e.hash = String::new();
+        let json = serde_json::to_string(&e).expect("Expect to serialize audit entry");
+        keccak256(json.as_bytes()).to_hex()
+    }
+}
+
+/// Reference to the last entry of audit log
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+struct Head {
+    seq: u64,
+    hash: String,
+}
+
+/// Audit log stored as JSON lines file
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    head_path: PathBuf,
+}
+
+impl AuditLog {
+    /// Create audit log for the base path
+    ///
+    /// # Arguments:
+    ///
+    /// * base_path - storage base path
+    ///
+    pub fn new<P: AsRef<Path>>(base_path: P) -> AuditLog {
+        AuditLog {
+            path: base_path.as_ref().join(AUDIT_LOG_FILE),
+            head_path: base_path.as_ref().join(AUDIT_HEAD_FILE),
+        }
+    }
+
+    /// Append new entry for an event and its result
+    ///
+    /// # Arguments:
+    ///
+    /// * event - recorded operation
+    /// * result - result of the operation
+    ///
+    pub fn record<T>(&self, event: Event, result: &Result<T, Error>) -> Result<Entry, Error> {
+        let outcome = match *result {
+            Ok(_) => "ok".to_string(),
+            Err(ref e) => format!("failed: {}", e),
+        };
+        self.append(event, outcome)
+    }
+
+    /// Append new entry chained to the last one. Log is locked
+    /// from reading the last entry until new one is written,
+    /// so concurrent processes don't fork the chain
+    pub fn append(&self, event: Event, outcome: String) -> Result<Entry, Error> {
+        let _lock = lock_file(&self.path)?;
+        let (seq, prev_hash) = match self.entries()?.last() {
+            Some(e) => (e.seq + 1, e.hash.clone()),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+
+        let mut entry = Entry {
+            seq,
+            timestamp: now(),
+            operation: event.operation,
+            chain: event.chain,
+            address: event.address,
+            tx_hash: event.tx_hash,
+            to: event.to,
+            value: event.value,
+            outcome,
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.digest();
+
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(f, "{}", serde_json::to_string(&entry)?)?;
+        f.sync_all()?;
+
+        let head = Head {
+            seq: entry.seq,
+            hash: entry.hash.clone(),
+        };
+        write_atomic(&self.head_path, serde_json::to_string(&head)?.as_bytes())?;
+
+        Ok(entry)
+    }
+
+    /// Reference to the last entry, if recorded
+    fn head(&self) -> Result<Option<Head>, Error> {
+        if !self.head_path.exists() {
+            return Ok(None);
+        }
+
+        let mut json = String::new();
+        File::open(&self.head_path).and_then(|mut f| f.read_to_string(&mut json))?;
+        serde_json::from_str(&json).map(Some).map_err(Error::from)
+    }
+
+    /// Read all entries
+    pub fn entries(&self) -> Result<Vec<Entry>, Error> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        let f = File::open(&self.path)?;
+        let mut entries = vec![];
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line)?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Check integrity of the whole chain
+    ///
+    /// # Return:
+    ///
+    /// * usize - number of verified entries
+    ///
+    pub fn verify(&self) -> Result<usize, Error> {
+        let entries = self.entries()?;
+        let mut prev_hash = GENESIS_HASH.to_string();
+
+        for (i, e) in entries.iter().enumerate() {
+            if e.seq != i as u64 {
+                return Err(Error::ExecError(format!(
+                    "Audit log broken at entry #{}: expected sequence number {}",
+                    e.seq, i
+                )));
+            }
+            if e.prev_hash != prev_hash {
+                return Err(Error::ExecError(format!(
+                    "Audit log broken at entry #{}: previous hash mismatch",
+                    e.seq
+                )));
+            }
+            if e.hash != e.digest() {
+                return Err(Error::ExecError(format!(
+                    "Audit log broken at entry #{}: content hash mismatch",
+                    e.seq
+                )));
+            }
+            prev_hash = e.hash.clone();
+        }
+
+        // head lags behind only if process stopped right after append
+        if let Some(head) = self.head()? {
+            match entries.get(head.seq as usize) {
+                Some(e) if e.hash == head.hash => {}
+                Some(_) => {
+                    return Err(Error::ExecError(format!(
+                        "Audit log broken at entry #{}: last entry hash mismatch",
+                        head.seq
+                    )))
+                }
+                None => {
+                    return Err(Error::ExecError(format!(
+                        "Audit log truncated: {} entries found, last recorded entry is #{}",
+                        entries.len(),
+                        head.seq
+                    )))
+                }
+            }
+        }
+
+        Ok(entries.len())
+    }
+}
+
+/// Seconds since Jan 01 1970 (UTC)
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::thread;
+    use tempdir::TempDir;
+
+    fn event() -> Event {
+        Event {
+            address: Some("0x0e7c045110b8dbf29765047380898919c5cb56f4".to_string()),
+            ..Event::new("account strip", "mainnet")
+        }
+    }
+
+    #[test]
+    fn should_chain_entries() {
+        let dir = TempDir::new("emerald").unwrap();
+        let log = AuditLog::new(dir.path());
+
+        let first = log.append(event(), "ok".to_string()).unwrap();
+        let second = log.append(event(), "ok".to_string()).unwrap();
+
+        assert_eq!(first.seq, 0);
+        assert_eq!(first.prev_hash, GENESIS_HASH);
+        assert_eq!(second.seq, 1);
+        assert_eq!(second.prev_hash, first.hash);
+        assert_eq!(log.verify().unwrap(), 2);
+    }
+
+    #[test]
+    fn should_detect_tampering() {
+        let dir = TempDir::new("emerald").unwrap();
+        let log = AuditLog::new(dir.path());
+        log.append(event(), "ok".to_string()).unwrap();
+        log.append(event(), "failed: invalid passphrase".to_string())
+            .unwrap();
+
+        let path = dir.path().join(AUDIT_LOG_FILE);
+        let mut content = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        let tampered = content.replace("failed: invalid passphrase", "ok");
+        File::create(&path)
+            .unwrap()
+            .write_all(tampered.as_bytes())
+            .unwrap();
+
+        assert!(log.verify().is_err());
+    }
+
+    #[test]
+    fn should_detect_truncation() {
+        let dir = TempDir::new("emerald").unwrap();
+        let log = AuditLog::new(dir.path());
+        log.append(event(), "ok".to_string()).unwrap();
+        log.append(event(), "ok".to_string()).unwrap();
+
+        let path = dir.path().join(AUDIT_LOG_FILE);
+        let mut content = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        let first = content.lines().next().unwrap();
+        File::create(&path)
+            .unwrap()
+            .write_all(format!("{}\n", first).as_bytes())
+            .unwrap();
+        assert!(log.verify().is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert!(log.verify().is_err());
+    }
+
+    #[test]
+    fn should_keep_chain_for_concurrent_writers() {
+        let dir = TempDir::new("emerald").unwrap();
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let log = AuditLog::new(dir.path());
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        log.append(event(), "ok".to_string()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+
+        assert_eq!(AuditLog::new(dir.path()).verify().unwrap(), 40);
+    }
+}
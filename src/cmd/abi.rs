@@ -0,0 +1,354 @@
+//! # Contract ABI encoding helpers
+//! Refer [Ethereum Contract ABI](https://github.com/ethereumproject/wiki/wiki/Ethereum-Contract-ABI)
+
+use super::arg_handlers::hex_to_32bytes;
+use super::units::{add_u256, from_u64, parse_units, to_decimal};
+use super::{Address, Error};
+use emerald::{keccak256, to_even_str, trim_hex};
+use hex::FromHex;
+use std::str::FromStr;
+
+/// Size of encoded ABI word
+pub const WORD_BYTES: usize = 32;
+
+/// Function selector, first 4 bytes of signature hash
+///
+/// # Arguments:
+///
+/// * signature - function signature, ex.: `transfer(address,uint256)`
+///
+pub fn selector(signature: &str) -> [u8; 4] {
+    let hash = keccak256(signature.as_bytes());
+    let mut res = [0u8; 4];
+    res.copy_from_slice(&hash[..4]);
+    res
+}
+
+/// Encode address into ABI word
+pub fn encode_address(addr: &Address) -> [u8; WORD_BYTES] {
+    let bytes = Vec::from_hex(&addr.to_string()[2..]).expect("Expect hex encoded address");
+    let mut res = [0u8; WORD_BYTES];
+    res[WORD_BYTES - bytes.len()..].copy_from_slice(&bytes);
+    res
+}
+
+/// Encode function call with static arguments
+///
+/// # Arguments:
+///
+/// * signature - function signature
+/// * args - encoded arguments
+///
+pub fn encode_call(signature: &str, args: &[[u8; WORD_BYTES]]) -> Vec<u8> {
+    let mut res = selector(signature).to_vec();
+    for a in args {
+        res.extend_from_slice(a);
+    }
+    res
+}
+
+/// Encode arguments of function or constructor. Static types and
+/// dynamic `string` and `bytes` are supported, arrays are not
+///
+/// # Arguments:
+///
+/// * types - argument types, ex.: `uint256`
+/// * values - argument values, integers as decimal or `0x` prefixed hex
+///
+pub fn encode_args(types: &[String], values: &[&str]) -> Result<Vec<u8>, Error> {
+    if types.len() != values.len() {
+        return Err(Error::ExecError(format!(
+            "Expected {} arguments, got {}",
+            types.len(),
+            values.len()
+        )));
+    }
+
+    let head_len = WORD_BYTES * types.len();
+    let mut head = vec![];
+    let mut tail = vec![];
+    for (t, v) in types.iter().zip(values.iter()) {
+        let dynamic = match t.as_str() {
+            "string" => Some(v.as_bytes().to_vec()),
+            "bytes" => Some(Vec::from_hex(trim_hex(v))?),
+            _ => None,
+        };
+        match dynamic {
+            Some(b) => {
+                head.extend_from_slice(&from_u64((head_len + tail.len()) as u64));
+                tail.extend_from_slice(&from_u64(b.len() as u64));
+                tail.extend_from_slice(&b);
+                let padding = (WORD_BYTES - b.len() % WORD_BYTES) % WORD_BYTES;
+                tail.extend(vec![0u8; padding]);
+            }
+            None => head.extend_from_slice(&encode_static(t, v)?),
+        }
+    }
+
+    head.extend(tail);
+    Ok(head)
+}
+
+/// Encode value of static type into ABI word
+fn encode_static(t: &str, v: &str) -> Result<[u8; WORD_BYTES], Error> {
+    let invalid = || Error::ExecError(format!("Invalid `{}` value: {}", t, v));
+    let unsupported = || Error::ExecError(format!("Unsupported ABI type: {}", t));
+
+    if t.contains('[') {
+        Err(unsupported())
+    } else if t == "address" {
+        Address::from_str(v)
+            .map(|a| encode_address(&a))
+            .map_err(|_| invalid())
+    } else if t == "bool" {
+        match v {
+            "true" => Ok(from_u64(1)),
+            "false" => Ok(from_u64(0)),
+            _ => Err(invalid()),
+        }
+    } else if t.starts_with("uint") {
+        let bits = int_bits(&t[4..]).ok_or_else(unsupported)?;
+        let n = parse_uint(v).map_err(|_| invalid())?;
+        if !fits_int(&n, bits, false, false) {
+            return Err(invalid());
+        }
+        Ok(n)
+    } else if t.starts_with("int") {
+        let bits = int_bits(&t[3..]).ok_or_else(unsupported)?;
+        let (sign, abs) = match v.chars().next() {
+            Some('-') => (true, &v[1..]),
+            _ => (false, v),
+        };
+        let mut n = parse_uint(abs).map_err(|_| invalid())?;
+        // `-0` is zero, not a negative number
+        let negative = sign && n != [0u8; WORD_BYTES];
+        if negative {
+            // two's complement of absolute value
+            for b in n.iter_mut() {
+                *b = !*b;
+            }
+            n = add_u256(&n, &from_u64(1))?;
+        }
+        if !fits_int(&n, bits, true, negative) {
+            return Err(invalid());
+        }
+        Ok(n)
+    } else if t.starts_with("bytes") {
+        let size = t[5..].parse::<usize>().map_err(|_| unsupported())?;
+        if size == 0 || size > WORD_BYTES {
+            return Err(unsupported());
+        }
+        let b = Vec::from_hex(trim_hex(v)).map_err(|_| invalid())?;
+        if b.len() != size {
+            return Err(invalid());
+        }
+        let mut res = [0u8; WORD_BYTES];
+        res[..size].copy_from_slice(&b);
+        Ok(res)
+    } else {
+        Err(unsupported())
+    }
+}
+
+/// Bit width of `uintN` or `intN` type from its suffix,
+/// empty suffix is an alias for 256 bits
+fn int_bits(suffix: &str) -> Option<usize> {
+    if suffix.is_empty() {
+        return Some(256);
+    }
+    suffix
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n >= 8 && *n <= 256 && n % 8 == 0)
+}
+
+/// Whether integer word fits into type of `bits` width
+///
+/// # Arguments:
+///
+/// * word - integer, negative values in two's complement
+/// * bits - bit width of type
+/// * signed - whether type is signed
+/// * negative - whether value is negative
+///
+fn fits_int(word: &[u8; WORD_BYTES], bits: usize, signed: bool, negative: bool) -> bool {
+    let size = bits / 8;
+    let pad = if negative { 0xff } else { 0 };
+    if !word[..WORD_BYTES - size].iter().all(|b| *b == pad) {
+        return false;
+    }
+    !signed || (word[WORD_BYTES - size] & 0x80 != 0) == negative
+}
+
+/// Parse unsigned integer, decimal or `0x` prefixed hex
+fn parse_uint(v: &str) -> Result<[u8; WORD_BYTES], Error> {
+    if v.starts_with("0x") {
+        let hex = to_even_str(trim_hex(v));
+        if hex.len() > 2 * WORD_BYTES {
+            return Err(Error::ExecError(format!("Integer overflow: {}", v)));
+        }
+        hex_to_32bytes(&hex)
+    } else {
+        parse_units(v, 0)
+    }
+}
+
+/// Decode unsigned integer from ABI word
+pub fn decode_uint(data: &[u8]) -> Result<[u8; WORD_BYTES], Error> {
+    if data.len() < WORD_BYTES {
+        return Err(Error::ExecError(
+            "Invalid ABI data: expected 32 bytes word".to_string(),
+        ));
+    }
+
+    let mut res = [0u8; WORD_BYTES];
+    res.copy_from_slice(&data[..WORD_BYTES]);
+    Ok(res)
+}
+
+/// Decode small unsigned integer from ABI word
+pub fn decode_u64(data: &[u8]) -> Result<u64, Error> {
+    let word = decode_uint(data)?;
+    to_decimal(&word)
+        .parse::<u64>()
+        .map_err(|_| Error::ExecError("Invalid ABI data: integer overflow".to_string()))
+}
+
+/// Decode `string` returned from a call. Non-standard contracts
+/// returning `bytes32` are supported too
+pub fn decode_string(data: &[u8]) -> Result<String, Error> {
+    let raw = if data.len() > 2 * WORD_BYTES {
+        let out_of_bounds =
+            || Error::ExecError("Invalid ABI data: string out of bounds".to_string());
+        // offset and length come from contract, check bounds before any arithmetic
+        let offset = decode_u64(data)?;
+        if offset > data.len() as u64 {
+            return Err(out_of_bounds());
+        }
+        let offset = offset as usize;
+        let len = decode_u64(&data[offset..])?;
+        let start = offset + WORD_BYTES;
+        if len > (data.len() - start) as u64 {
+            return Err(out_of_bounds());
+        }
+        data[start..start + len as usize].to_vec()
+    } else {
+        decode_uint(data)?
+            .iter()
+            .cloned()
+            .take_while(|b| *b != 0)
+            .collect()
+    };
+
+    String::from_utf8(raw).map_err(|e| Error::ExecError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::ToHex;
+    use std::str::FromStr;
+
+    #[test]
+    fn should_compute_selector() {
+        assert_eq!(selector("transfer(address,uint256)").to_hex(), "a9059cbb");
+        assert_eq!(selector("balanceOf(address)").to_hex(), "70a08231");
+    }
+
+    #[test]
+    fn should_encode_address() {
+        let addr = Address::from_str("0x0e7c045110b8dbf29765047380898919c5cb56f4").unwrap();
+        assert_eq!(
+            encode_address(&addr).to_hex(),
+            "0000000000000000000000000e7c045110b8dbf29765047380898919c5cb56f4"
+        );
+    }
+
+    #[test]
+    fn should_decode_string() {
+        let mut data = vec![0u8; 96];
+        data[31] = 0x20;
+        data[63] = 3;
+        data[64..67].copy_from_slice(b"ETC");
+        assert_eq!(decode_string(&data).unwrap(), "ETC");
+
+        let mut data = vec![0u8; 32];
+        data[..3].copy_from_slice(b"DGD");
+        assert_eq!(decode_string(&data).unwrap(), "DGD");
+    }
+
+    #[test]
+    fn should_fail_to_decode_string_out_of_bounds() {
+        let mut data = vec![0u8; 96];
+        data[31] = 0x20;
+        data[56..64].copy_from_slice(&[0xff; 8]);
+        assert!(decode_string(&data).is_err());
+
+        let mut data = vec![0u8; 96];
+        data[24..32].copy_from_slice(&[0xff; 8]);
+        assert!(decode_string(&data).is_err());
+
+        let mut data = vec![0u8; 96];
+        data[31] = 0x50;
+        assert!(decode_string(&data).is_err());
+    }
+
+    #[test]
+    fn should_encode_static_args() {
+        let types: Vec<String> = ["address", "uint256", "bool", "int8", "bytes2"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        let data = encode_args(
+            &types,
+            &["0x0e7c045110b8dbf29765047380898919c5cb56f4", "0x10", "true", "-1", "0xabcd"],
+        ).unwrap();
+
+        assert_eq!(data.len(), 5 * WORD_BYTES);
+        assert_eq!(
+            data[..32].to_hex(),
+            "0000000000000000000000000e7c045110b8dbf29765047380898919c5cb56f4"
+        );
+        assert_eq!(data[63], 0x10);
+        assert_eq!(data[95], 1);
+        assert!(data[96..128].iter().all(|b| *b == 0xff));
+        assert_eq!(&data[128..130], &[0xab, 0xcd]);
+
+        assert!(encode_args(&types[..1], &[]).is_err());
+        assert!(encode_args(&types[2..3], &["yes"]).is_err());
+        assert!(encode_args(&["uint256[]".to_string()], &["1"]).is_err());
+        assert!(encode_args(&["int8[2]".to_string()], &["1"]).is_err());
+    }
+
+    #[test]
+    fn should_check_integer_range() {
+        assert!(encode_static("uint8", "255").is_ok());
+        assert!(encode_static("uint8", "300").is_err());
+        assert!(encode_static("uint7", "1").is_err());
+        assert!(encode_static("uint264", "1").is_err());
+        assert_eq!(encode_static("uint", "0x10").unwrap()[31], 0x10);
+
+        assert_eq!(encode_static("int8", "127").unwrap()[31], 0x7f);
+        assert!(encode_static("int8", "128").is_err());
+        assert_eq!(encode_static("int8", "-128").unwrap()[31], 0x80);
+        assert!(encode_static("int8", "-129").is_err());
+        assert_eq!(encode_static("int16", "-0").unwrap(), [0u8; WORD_BYTES]);
+        assert!(encode_static("int256", "-1")
+            .unwrap()
+            .iter()
+            .all(|b| *b == 0xff));
+    }
+
+    #[test]
+    fn should_encode_dynamic_args() {
+        let types = vec!["uint256".to_string(), "string".to_string()];
+        let data = encode_args(&types, &["123", "ETC"]).unwrap();
+
+        assert_eq!(data.len(), 4 * WORD_BYTES);
+        assert_eq!(data[31], 123);
+        // offset of string data
+        assert_eq!(data[63], 0x40);
+        assert_eq!(data[95], 3);
+        assert_eq!(&data[96..99], b"ETC");
+    }
+}
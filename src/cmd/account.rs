@@ -1,8 +1,14 @@
 //! # Account related subcommands
 
 use super::arg_handlers::*;
+use super::chain::Chain;
 use super::emerald::storage::KeystoreError;
-use super::{EnvVars, Error, ExecResult, KeyfileStorage};
+use super::integrity::{check_keystore, quarantine, KeyfileStatus, QUARANTINE_DIR};
+use super::storage::Storages;
+use super::units::{add_u256, format_units};
+use super::{rpc, EnvVars, Error, ExecResult, KeyfileStorage};
+use audit::{AuditLog, Event};
+use emerald::{to_even_str, trim_hex};
 use indicator::ProgressIndicator;
 use serde_json;
 use std::fs::File;
@@ -20,23 +26,30 @@ use std::str::FromStr;
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
-/// * storage - `Keyfile` storage
+/// * storages - keystores for all chains
 /// * env - environment variables
+/// * audit - audit log
+/// * chain - selected chain
 ///
 pub fn account_cmd(
     matches: &ArgMatches,
-    storage: &Box<KeyfileStorage>,
+    storages: &Storages,
     env: &EnvVars,
+    audit: &AuditLog,
+    chain: &Chain,
 ) -> ExecResult {
+    let storage = storages.get_keystore(&chain.name)?;
     match matches.subcommand() {
-        ("list", Some(sub_m)) => list(sub_m, storage),
-        ("new", Some(sub_m)) => new(sub_m, storage),
+        ("list", Some(sub_m)) => list(sub_m, storage, env, chain),
+        ("new", Some(sub_m)) => new(sub_m, storage, env),
         ("hide", Some(sub_m)) => toggle_visibility(sub_m, storage, |a| storage.hide(a)),
         ("unhide", Some(sub_m)) => toggle_visibility(sub_m, storage, |a| storage.unhide(a)),
-        ("strip", Some(sub_m)) => strip(sub_m, storage),
-        ("import", Some(sub_m)) => import(sub_m, storage, env),
-        ("export", Some(sub_m)) => export(sub_m, storage, env),
+        ("strip", Some(sub_m)) => strip(sub_m, storage, audit, &chain.name),
+        ("verify", Some(sub_m)) => verify(sub_m, storage, audit, &chain.name),
+        ("import", Some(sub_m)) => import(sub_m, storage),
+        ("export", Some(sub_m)) => export(sub_m, storage),
         ("update", Some(sub_m)) => update(sub_m, storage),
+        ("check", Some(sub_m)) => check(sub_m, storages, &chain.name),
         _ => Err(Error::ExecError(
             "Invalid account subcommand. Use `emerald account -h` for help".to_string(),
         )),
@@ -49,8 +62,19 @@ pub fn account_cmd(
 ///
 /// * matches - arguments supplied from command-line
 /// * storage - `Keyfile` storage
+/// * env - environment variables
+/// * chain - selected chain
 ///
-fn list(matches: &ArgMatches, storage: &Box<KeyfileStorage>) -> ExecResult {
+fn list(
+    matches: &ArgMatches,
+    storage: &Box<KeyfileStorage>,
+    env: &EnvVars,
+    chain: &Chain,
+) -> ExecResult {
+    if matches.is_present("with-balance") {
+        return balances(matches, storage, env, chain);
+    }
+
     let accounts_info = storage.list_accounts(matches.is_present("show-hidden"))?;
 
     println!("{0: <45} {1: <45} ", "ADDRESS", "NAME");
@@ -61,19 +85,74 @@ fn list(matches: &ArgMatches, storage: &Box<KeyfileStorage>) -> ExecResult {
     Ok(())
 }
 
+/// Print balances and nonces of keystore accounts with total balance.
+/// All values are requested from remote node in one batch
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storage - `Keyfile` storage
+/// * env - environment variables
+/// * chain - selected chain
+///
+pub fn balances(
+    matches: &ArgMatches,
+    storage: &Box<KeyfileStorage>,
+    env: &EnvVars,
+    chain: &Chain,
+) -> ExecResult {
+    let block = get_block(matches)?;
+    let rpc = get_upstream(matches, env)?;
+
+    let accounts_info = storage.list_accounts(matches.is_present("show-hidden"))?;
+    let mut addrs = vec![];
+    for info in &accounts_info {
+        addrs.push(Address::from_str(&info.address)?);
+    }
+    let states = rpc::request_accounts(&rpc, &addrs, &block)?;
+
+    let mut total = [0u8; 32];
+    println!(
+        "{0: <45} {1: <20} {2: >30} {3: >8}",
+        "ADDRESS", "NAME", "BALANCE", "NONCE"
+    );
+    for (info, state) in accounts_info.iter().zip(states) {
+        let balance = hex_to_32bytes(&to_even_str(trim_hex(&state.balance)))?;
+        let nonce = u64::from_str_radix(trim_hex(&state.nonce), 16)?;
+        total = add_u256(&total, &balance)?;
+
+        println!(
+            "{0: <45} {1: <20} {2: >30} {3: >8}",
+            &info.address,
+            &info.name,
+            format!("{} {}", format_units(&balance, chain.decimals), chain.symbol),
+            nonce
+        );
+    }
+    println!(
+        "{0: <45} {1: <20} {2: >30}",
+        "TOTAL",
+        "",
+        format!("{} {}", format_units(&total, chain.decimals), chain.symbol)
+    );
+
+    Ok(())
+}
+
 /// Creates new account
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
 /// * storage - `Keyfile` storage
+/// * env - environment variables
 ///
-fn new(matches: &ArgMatches, storage: &Box<KeyfileStorage>) -> ExecResult {
+fn new(matches: &ArgMatches, storage: &Box<KeyfileStorage>, env: &EnvVars) -> ExecResult {
     println!("! Warning: passphrase can't be restored. Don't forget it !");
     let passphrase = request_passphrase()?;
     let name = matches.value_of("name").map(String::from);
     let desc = matches.value_of("description").map(String::from);
-    let sec_level = get_security_lvl(matches)?;
+    let sec_level = get_security_lvl(matches, env)?;
     info!("Security level: {}", sec_level);
 
     let ind = ProgressIndicator::start(Some("Generating new account".to_string()));
@@ -127,29 +206,158 @@ fn toggle_visibility<U, F: Fn(&Address) -> Result<U, KeystoreError>>(
 ///
 /// * matches - arguments supplied from command-line
 /// * storage - `Keyfile` storage
+/// * audit - audit log
+/// * chain - chain name
 ///
-fn strip(matches: &ArgMatches, storage: &Box<KeyfileStorage>) -> ExecResult {
+fn strip(
+    matches: &ArgMatches,
+    storage: &Box<KeyfileStorage>,
+    audit: &AuditLog,
+    chain: &str,
+) -> ExecResult {
     let address = get_address(matches, "address")?;
 
     let (_, kf) = storage.search_by_address(&address)?;
     let passphrase = request_passphrase()?;
-    let pk = kf.decrypt_key(&passphrase)?;
+    let pk = kf.decrypt_key(&passphrase).map_err(Error::from);
+
+    let event = Event {
+        address: Some(address.to_string()),
+        ..Event::new("account strip", chain)
+    };
+    audit.record(event, &pk)?;
+    let pk = pk?;
 
     println!("Private key: {}", &pk.to_string());
 
     Ok(())
 }
 
+/// Verify passphrase of account without showing private key.
+/// Fails if key can't be decrypted or doesn't match keyfile address
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storage - `Keyfile` storage
+/// * audit - audit log
+/// * chain - chain name
+///
+fn verify(
+    matches: &ArgMatches,
+    storage: &Box<KeyfileStorage>,
+    audit: &AuditLog,
+    chain: &str,
+) -> ExecResult {
+    let address = get_address(matches, "address")?;
+
+    let (_, kf) = storage.search_by_address(&address)?;
+    let passphrase = request_passphrase()?;
+    let res = verify_key(&kf, &passphrase);
+
+    let event = Event {
+        address: Some(address.to_string()),
+        ..Event::new("account verify", chain)
+    };
+    audit.record(event, &res)?;
+    res?;
+
+    println!("Passphrase is valid for {}", &address);
+
+    Ok(())
+}
+
+/// Decrypt private key and check it belongs to keyfile address
+///
+/// # Arguments:
+///
+/// * kf - keyfile
+/// * passphrase - passphrase for keyfile
+///
+fn verify_key(kf: &KeyFile, passphrase: &str) -> ExecResult {
+    let pk = kf.decrypt_key(passphrase)?;
+    if pk.to_address()? != kf.address {
+        return Err(Error::ExecError(
+            "Decrypted private key doesn't match keyfile address".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check integrity of all keyfiles in keystore.
+/// Fails if any keyfile is invalid or duplicated, unless moved to quarantine
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - keystores for all chains
+/// * chain - chain name
+///
+fn check(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    let dir = storages.keyfile_dir(chain);
+    let passphrase = if matches.is_present("verify") {
+        Some(request_passphrase()?)
+    } else {
+        None
+    };
+    let passphrase = passphrase.as_ref().map(String::as_str);
+    let checks = if dir.exists() {
+        check_keystore(&dir, passphrase)?
+    } else {
+        vec![]
+    };
+
+    let mut problems = 0;
+    let mut moved = 0;
+    println!("{0: <60} {1: <44} STATUS", "FILE", "ADDRESS");
+    for c in checks.iter() {
+        let status = match c.status {
+            KeyfileStatus::Valid => "ok".to_string(),
+            KeyfileStatus::Verified => "ok, verified".to_string(),
+            KeyfileStatus::Locked => "ok, not verified: wrong passphrase".to_string(),
+            KeyfileStatus::Duplicate(ref f) => format!("duplicate of {}", f),
+            KeyfileStatus::Invalid(ref e) => format!("invalid: {}", e),
+        };
+        let address = c.address.as_ref().map(String::as_str).unwrap_or("");
+        println!("{0: <60} {1: <44} {2}", &c.file, address, status);
+
+        match c.status {
+            KeyfileStatus::Invalid(_) if matches.is_present("quarantine") => {
+                let target = quarantine(&dir, &c.file, &dir.with_file_name(QUARANTINE_DIR))?;
+                println!("  moved to {}", target.display());
+                moved += 1;
+            }
+            ref s if s.is_problem() => problems += 1,
+            _ => {}
+        }
+    }
+
+    println!(
+        "Checked {} file(s): {} problem(s), {} moved to quarantine",
+        checks.len(),
+        problems,
+        moved
+    );
+    if problems > 0 {
+        return Err(Error::ExecError(format!(
+            "Keystore has {} problem(s)",
+            problems
+        )));
+    }
+
+    Ok(())
+}
+
 /// Export accounts
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
 /// * storage - `Keyfile` storage
-/// * env - environment variables
 ///
-fn export(matches: &ArgMatches, storage: &Box<KeyfileStorage>, env: &EnvVars) -> ExecResult {
-    let path = get_path(matches, env)?;
+fn export(matches: &ArgMatches, storage: &Box<KeyfileStorage>) -> ExecResult {
+    let path = get_path(matches)?;
 
     let ind = ProgressIndicator::start(Some("Exporting Keyfiles".to_string()));
     if matches.is_present("all") {
@@ -178,10 +386,9 @@ fn export(matches: &ArgMatches, storage: &Box<KeyfileStorage>, env: &EnvVars) ->
 ///
 /// * matches - arguments supplied from command-line
 /// * storage - `Keyfile` storage
-/// * env - environment variables
 ///
-fn import(matches: &ArgMatches, storage: &Box<KeyfileStorage>, env: &EnvVars) -> ExecResult {
-    let path = get_path(matches, env)?;
+fn import(matches: &ArgMatches, storage: &Box<KeyfileStorage>) -> ExecResult {
+    let path = get_path(matches)?;
     let mut counter = 0;
 
     let ind = ProgressIndicator::start(Some("Importing Keyfiles".to_string()));
@@ -223,18 +430,16 @@ fn update(matches: &ArgMatches, storage: &Box<KeyfileStorage>) -> ExecResult {
     Ok(())
 }
 
-/// Parse address from command-line argument
+/// Parse import/export path from command-line argument
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
-/// * env - environment variables
 ///
-fn get_path(matches: &ArgMatches, env: &EnvVars) -> Result<PathBuf, Error> {
+fn get_path(matches: &ArgMatches) -> Result<PathBuf, Error> {
     matches
         .value_of("path")
-        .or_else(|| env.emerald_base_path.as_ref().map(String::as_str))
-        .and_then(|p| Some(PathBuf::from(p)))
+        .map(PathBuf::from)
         .ok_or_else(|| Error::ExecError("Expected path".to_string()))
 }
 
@@ -0,0 +1,220 @@
+//! # Address book related subcommands
+
+use super::arg_handlers::*;
+use super::storage::Storages;
+use super::{Address, Error, ExecResult};
+use clap::ArgMatches;
+use serde_json::Value;
+use std::str::FromStr;
+
+/// Address book entry
+#[derive(Clone, Debug)]
+pub struct Contact {
+    pub address: Address,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Contact {
+    /// Parse address book `JSON` entry
+    fn from_json(v: &Value) -> Option<Contact> {
+        let address = v
+            .get("id")
+            .or_else(|| v.get("address"))
+            .and_then(Value::as_str)
+            .and_then(|s| Address::from_str(s).ok())?;
+
+        Some(Contact {
+            address,
+            name: v.get("name").and_then(Value::as_str).map(String::from),
+            description: v
+                .get("description")
+                .and_then(Value::as_str)
+                .map(String::from),
+        })
+    }
+
+    /// Convert into address book `JSON` entry
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.address.to_string(),
+            "name": self.name,
+            "description": self.description,
+        })
+    }
+}
+
+/// Address book commands
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+pub fn address_cmd(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    match matches.subcommand() {
+        ("add", Some(sub_m)) => add(sub_m, storages, chain),
+        ("list", Some(_)) => list(storages, chain),
+        ("remove", Some(sub_m)) => remove(sub_m, storages, chain),
+        ("rename", Some(sub_m)) => rename(sub_m, storages, chain),
+        _ => Err(Error::ExecError(
+            "Invalid address subcommand. Use `emerald address -h` for help".to_string(),
+        )),
+    }
+}
+
+/// Add new address into address book
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+fn add(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    let address = get_address(matches, "address")?;
+    let name = matches.value_of("name").map(String::from);
+
+    if let Some(ref n) = name {
+        if find_contact(n, storages, chain)?.is_some() {
+            return Err(Error::ExecError(format!("Name already in use: {}", n)));
+        }
+    }
+
+    let contact = Contact {
+        address,
+        name,
+        description: matches.value_of("description").map(String::from),
+    };
+    storages
+        .get_addressbook(chain)?
+        .add(&contact.to_json())
+        .map_err(|e| Error::ExecError(e.to_string()))?;
+
+    println!("Added address: {}", &contact.address);
+
+    Ok(())
+}
+
+/// List all addresses from address book
+///
+/// # Arguments:
+///
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+fn list(storages: &Storages, chain: &str) -> ExecResult {
+    println!("{0: <45} {1: <30} {2}", "ADDRESS", "NAME", "DESCRIPTION");
+    for c in list_contacts(storages, chain)? {
+        println!(
+            "{0: <45} {1: <30} {2}",
+            &c.address.to_string(),
+            c.name.unwrap_or_default(),
+            c.description.unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+/// Remove address from address book
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+fn remove(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    let contact = get_contact(matches, storages, chain)?;
+    storages
+        .get_addressbook(chain)?
+        .delete(&contact.to_json())
+        .map_err(|e| Error::ExecError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Change name of address book entry
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+fn rename(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    let mut contact = get_contact(matches, storages, chain)?;
+    let name = matches
+        .value_of("name")
+        .expect("Required new name")
+        .to_string();
+
+    if let Some(c) = find_contact(&name, storages, chain)? {
+        if c.address != contact.address {
+            return Err(Error::ExecError(format!("Name already in use: {}", name)));
+        }
+    }
+
+    contact.name = Some(name);
+    storages
+        .get_addressbook(chain)?
+        .edit(&contact.to_json())
+        .map_err(|e| Error::ExecError(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Get address book entry for `address` argument,
+/// which can be either an address or a name
+fn get_contact(matches: &ArgMatches, storages: &Storages, chain: &str) -> Result<Contact, Error> {
+    let s = matches
+        .value_of("address")
+        .expect("Required address or name");
+    let address = resolve_address(s, storages, chain)?;
+
+    list_contacts(storages, chain)?
+        .into_iter()
+        .find(|c| c.address == address)
+        .ok_or_else(|| Error::ExecError(format!("Address not found in address book: {}", s)))
+}
+
+/// List all address book entries for selected chain
+///
+/// # Arguments:
+///
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+pub fn list_contacts(storages: &Storages, chain: &str) -> Result<Vec<Contact>, Error> {
+    let entries = storages.get_addressbook(chain)?.list();
+    Ok(entries.iter().filter_map(Contact::from_json).collect())
+}
+
+/// Search address book entry by name
+fn find_contact(name: &str, storages: &Storages, chain: &str) -> Result<Option<Contact>, Error> {
+    let contact = list_contacts(storages, chain)?
+        .into_iter()
+        .find(|c| c.name.as_ref().map(String::as_str) == Some(name));
+
+    Ok(contact)
+}
+
+/// Resolve hex address or address book name into `Address`
+///
+/// # Arguments:
+///
+/// * s - hex encoded address or name from address book
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+pub fn resolve_address(s: &str, storages: &Storages, chain: &str) -> Result<Address, Error> {
+    if let Ok(addr) = Address::from_str(s) {
+        return Ok(addr);
+    }
+
+    find_contact(s, storages, chain)?
+        .map(|c| c.address)
+        .ok_or_else(|| Error::ExecError(format!("Unknown address or name: {}", s)))
+}
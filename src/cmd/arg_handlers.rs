@@ -1,19 +1,30 @@
 //! # Helpers for command execution
 
+use super::config::Profile;
 use super::Error;
 use super::{
     align_bytes, to_arr, to_even_str, trim_hex, Address, ArgMatches, KdfDepthLevel, PrivateKey,
-    DEFAULT_UPSTREAM,
+    DEFAULT_HOST, DEFAULT_PORT, DEFAULT_UPSTREAM,
 };
 use hex::FromHex;
 use reqwest::Url;
 use rpassword;
-use rpc::{self, RpcConnector};
+use rpc::{self, HttpOptions, RpcConnector};
 use std::env;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
 use std::str::FromStr;
+use std::time::Duration;
+use url::percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET};
 
-/// Environment variables used to change default variables
+/// Supported schemes for upstream URL
+const UPSTREAM_SCHEMES: &[&str] = &["http", "https", "ws", "wss", "ipc"];
+
+/// Length of `0x`-prefixed block hash
+const BLOCK_HASH_LEN: usize = 66;
+
+/// Environment variables used to change default variables.
+/// Missing values can be filled from selected config profile
 #[derive(Default, Debug)]
 pub struct EnvVars {
     pub emerald_base_path: Option<String>,
@@ -23,8 +34,16 @@ pub struct EnvVars {
     pub emerald_chain_id: Option<String>,
     pub emerald_gas: Option<String>,
     pub emerald_gas_price: Option<String>,
+    pub emerald_max_gas_price: Option<String>,
     pub emerald_security_level: Option<String>,
     pub emerald_node: Option<String>,
+    pub emerald_profile: Option<String>,
+    pub emerald_output: Option<String>,
+    pub emerald_timeout: Option<String>,
+    pub emerald_connect_timeout: Option<String>,
+    pub emerald_retries: Option<String>,
+    pub emerald_proxy: Option<String>,
+    pub emerald_headers: Option<String>,
 }
 
 impl EnvVars {
@@ -40,13 +59,53 @@ impl EnvVars {
                 "EMERALD_CHAIN_ID" => vars.emerald_chain_id = Some(value),
                 "EMERALD_GAS" => vars.emerald_gas = Some(value),
                 "EMERALD_GAS_PRICE" => vars.emerald_gas_price = Some(value),
+                "EMERALD_MAX_GAS_PRICE" => vars.emerald_max_gas_price = Some(value),
                 "EMERALD_SECURITY_LEVEL" => vars.emerald_security_level = Some(value),
                 "EMERALD_NODE" => vars.emerald_node = Some(value),
+                "EMERALD_PROFILE" => vars.emerald_profile = Some(value),
+                "EMERALD_OUTPUT" => vars.emerald_output = Some(value),
+                "EMERALD_TIMEOUT" => vars.emerald_timeout = Some(value),
+                "EMERALD_CONNECT_TIMEOUT" => vars.emerald_connect_timeout = Some(value),
+                "EMERALD_RETRIES" => vars.emerald_retries = Some(value),
+                "EMERALD_PROXY" => vars.emerald_proxy = Some(value),
+                "EMERALD_HEADERS" => vars.emerald_headers = Some(value),
                 _ => (),
             }
         }
         vars
     }
+
+    /// Fill values missing in environment from config profile
+    ///
+    /// # Arguments:
+    ///
+    /// * profile - selected config profile
+    ///
+    pub fn merge_profile(&mut self, profile: &Profile) {
+        fn merge(var: &mut Option<String>, val: &Option<String>) {
+            if var.is_none() {
+                *var = val.clone();
+            }
+        }
+
+        merge(&mut self.emerald_chain, &profile.chain);
+        merge(&mut self.emerald_node, &profile.upstream);
+        merge(&mut self.emerald_gas, &profile.gas);
+        merge(&mut self.emerald_gas_price, &profile.gas_price);
+        merge(&mut self.emerald_max_gas_price, &profile.max_gas_price);
+        merge(&mut self.emerald_security_level, &profile.security_level);
+        merge(&mut self.emerald_output, &profile.output);
+        merge(&mut self.emerald_timeout, &profile.timeout);
+        merge(&mut self.emerald_connect_timeout, &profile.connect_timeout);
+        merge(&mut self.emerald_retries, &profile.retries);
+        merge(&mut self.emerald_proxy, &profile.proxy);
+        merge(&mut self.emerald_headers, &profile.headers);
+    }
+
+    /// Check whether `JSON` output format is selected
+    pub fn is_json_output(&self) -> bool {
+        self.emerald_output.as_ref().map(String::as_str) == Some("json")
+    }
 }
 
 /// Parse raw hex string arguments from user
@@ -77,55 +136,56 @@ pub fn hex_to_32bytes(hex: &str) -> Result<[u8; 32], Error> {
     Ok(to_arr(&align_bytes(&bytes, 32)))
 }
 
-/// Parse address from command-line argument
+/// Get nonce value for provided address
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
 /// * env - environment variables
+/// * addr - account address
 ///
-pub fn get_gas_price(matches: &ArgMatches, env: &EnvVars) -> Result<[u8; 32], Error> {
-    let gas_pr = match matches
-        .value_of("gas-price")
-        .or_else(|| env.emerald_gas_price.as_ref().map(String::as_str))
-    {
-        Some(g) => g.to_string(),
-        None => get_upstream(matches).and_then(|rpc| rpc::request_gas_price(&rpc))?,
+pub fn get_nonce(matches: &ArgMatches, env: &EnvVars, addr: &Address) -> Result<u64, Error> {
+    let nonce = match matches.value_of("nonce") {
+        Some(n) => n.to_string(),
+        None => {
+            let block = get_block(matches)?;
+            get_upstream(matches, env).and_then(|rpc| rpc::request_nonce(&rpc, addr, &block))?
+        }
     };
-    hex_to_32bytes(trim_hex(&gas_pr))
+    u64::from_str_radix(trim_hex(&nonce), 16).map_err(Error::from)
 }
 
-/// Parse address from command-line argument
+/// Get block for state queries: decimal or hex number, block hash,
+/// `latest`, `earliest` or `pending`. Latest block is used by default
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
-/// * env - environment variables
 ///
-pub fn get_gas_limit(matches: &ArgMatches, env: &EnvVars) -> Result<u64, Error> {
-    let gas = match matches
-        .value_of("gas")
-        .or_else(|| env.emerald_gas.as_ref().map(String::as_str))
-    {
-        Some(g) => g.to_string(),
-        None => get_upstream(matches).and_then(|rpc| rpc::request_gas(&rpc))?,
+pub fn get_block(matches: &ArgMatches) -> Result<String, Error> {
+    let block = match matches.value_of("block") {
+        Some(b) => b.trim(),
+        None => return Ok("latest".to_string()),
     };
-    u64::from_str_radix(trim_hex(&gas), 16).map_err(Error::from)
-}
 
-/// Get nonce value for provided address
-///
-/// # Arguments:
-///
-/// * matches - arguments supplied from command-line
-/// * addr - account address
-///
-pub fn get_nonce(matches: &ArgMatches, addr: &Address) -> Result<u64, Error> {
-    let nonce = match matches.value_of("nonce") {
-        Some(n) => n.to_string(),
-        None => get_upstream(matches).and_then(|rpc| rpc::request_nonce(&rpc, addr))?,
-    };
-    u64::from_str_radix(trim_hex(&nonce), 16).map_err(Error::from)
+    match block {
+        "latest" | "earliest" | "pending" => Ok(block.to_string()),
+        b if b.starts_with("0x") && b.len() == BLOCK_HASH_LEN => {
+            Vec::from_hex(trim_hex(b))?;
+            Ok(b.to_lowercase())
+        }
+        b if b.starts_with("0x") => {
+            let n = u64::from_str_radix(trim_hex(b), 16)?;
+            Ok(format!("0x{:x}", n))
+        }
+        b => match b.parse::<u64>() {
+            Ok(n) => Ok(format!("0x{:x}", n)),
+            Err(_) => Err(Error::ExecError(format!(
+                "Invalid block `{}`, expected number, hash, `latest`, `earliest` or `pending`",
+                b
+            ))),
+        },
+    }
 }
 
 /// Parse address from command-line argument
@@ -143,27 +203,159 @@ pub fn get_address(matches: &ArgMatches, arg_name: &str) -> Result<Address, Erro
     Address::from_str(s).map_err(Error::from)
 }
 
-/// Parse address from command-line argument
+/// Get connector to remote nodes from command-line arguments
+/// or environment variables. Upstream can be repeated, or specified
+/// as a comma-separated list
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
+/// * env - environment variables
 ///
-pub fn get_upstream(matches: &ArgMatches) -> Result<RpcConnector, Error> {
-    let ups = matches.value_of("upstream").unwrap_or(DEFAULT_UPSTREAM);
-    parse_socket(ups)
-        .or_else(|_| parse_url(ups))
-        .and_then(|url| Ok(RpcConnector { url }))
+pub fn get_upstream(matches: &ArgMatches, env: &EnvVars) -> Result<RpcConnector, Error> {
+    let ups: Vec<&str> = match matches.values_of("upstream") {
+        Some(vals) => vals.collect(),
+        None => vec![
+            env.emerald_node
+                .as_ref()
+                .map(String::as_str)
+                .unwrap_or(DEFAULT_UPSTREAM),
+        ],
+    };
+
+    let mut urls = vec![];
+    for u in ups.iter().flat_map(|u| u.split(',')).map(str::trim) {
+        if u.is_empty() {
+            continue;
+        }
+        urls.push(parse_upstream(u)?);
+    }
+    if urls.is_empty() {
+        return Err(Error::ExecError(
+            "No upstream specified, use `--upstream` or `EMERALD_NODE`".to_string(),
+        ));
+    }
+
+    RpcConnector::new(urls, matches.is_present("quorum"), &get_http_options(matches, env)?)
 }
 
-/// Parse address from command-line argument
+/// Get HTTP client settings from command-line arguments
+/// or environment variables
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+///
+pub fn get_http_options(matches: &ArgMatches, env: &EnvVars) -> Result<HttpOptions, Error> {
+    let arg = |name: &str, var: &Option<String>| -> Option<String> {
+        matches
+            .value_of(name)
+            .map(String::from)
+            .or_else(|| var.clone())
+    };
+
+    let mut opts = HttpOptions::default();
+    if let Some(t) = arg("timeout", &env.emerald_timeout) {
+        opts.timeout = Duration::from_secs(t.parse::<u64>()?);
+    }
+    if let Some(t) = arg("connect-timeout", &env.emerald_connect_timeout) {
+        opts.connect_timeout = Duration::from_secs(t.parse::<u64>()?);
+    }
+    if let Some(r) = arg("retries", &env.emerald_retries) {
+        opts.retries = r.parse::<u32>()?;
+    }
+    opts.proxy = arg("proxy", &env.emerald_proxy);
+
+    let headers: Vec<String> = match matches.values_of("header") {
+        Some(vals) => vals.map(String::from).collect(),
+        None => env.emerald_headers
+            .as_ref()
+            .map(|h| split_headers(h))
+            .unwrap_or_default(),
+    };
+    for h in headers.iter().filter(|h| !h.trim().is_empty()) {
+        opts.headers.push(parse_header(h)?);
+    }
+
+    Ok(opts)
+}
+
+/// Split list of headers separated by `;`. Part which doesn't start
+/// with `Name:` belongs to value of previous header, ex. `Cookie: a=1; b=2`
+fn split_headers(s: &str) -> Vec<String> {
+    let is_token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    let mut headers: Vec<String> = vec![];
+    for part in s.split(';') {
+        let starts_header = match part.find(':') {
+            Some(i) => {
+                let name = part[..i].trim();
+                !name.is_empty() && name.chars().all(is_token)
+            }
+            None => false,
+        };
+        if !starts_header {
+            if let Some(last) = headers.last_mut() {
+                last.push(';');
+                last.push_str(part);
+                continue;
+            }
+        }
+        headers.push(part.to_string());
+    }
+    headers
+}
+
+/// Parse HTTP header in `Name: value` format
+pub fn parse_header(s: &str) -> Result<(String, String), Error> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(Error::ExecError(format!(
+            "Invalid header `{}`, expected `Name: value`",
+            s
+        ))),
+    }
+}
+
+/// Get listen address for RPC server from command-line arguments
+/// or environment variables
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+///
+pub fn get_server_addr(matches: &ArgMatches, env: &EnvVars) -> Result<SocketAddr, Error> {
+    let host = matches
+        .value_of("host")
+        .or_else(|| env.emerald_host.as_ref().map(String::as_str))
+        .unwrap_or(DEFAULT_HOST);
+    let port = matches
+        .value_of("port")
+        .or_else(|| env.emerald_port.as_ref().map(String::as_str))
+        .unwrap_or(DEFAULT_PORT);
+
+    format!("{}:{}", host, port)
+        .parse::<SocketAddr>()
+        .map_err(Error::from)
+}
+
+/// Get key derivation depth from command-line argument
+/// or environment variables
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
+/// * env - environment variables
 ///
-pub fn get_security_lvl(matches: &ArgMatches) -> Result<KdfDepthLevel, Error> {
-    let kdf = match matches.value_of("security-level") {
+pub fn get_security_lvl(matches: &ArgMatches, env: &EnvVars) -> Result<KdfDepthLevel, Error> {
+    let kdf = match matches
+        .value_of("security-level")
+        .or_else(|| env.emerald_security_level.as_ref().map(String::as_str))
+    {
         Some(sec) => KdfDepthLevel::from_str(sec)?,
         None => KdfDepthLevel::default(),
     };
@@ -195,25 +387,85 @@ pub fn parse_data(s: &str) -> Result<Vec<u8>, Error> {
     }
 }
 
+/// Prefix of IPC upstream
+const IPC_PREFIX: &str = "ipc://";
+
+/// Parse address of ethereum node: URL with scheme, bare `host:port`,
+/// or path to IPC socket of local node
+pub fn parse_upstream(s: &str) -> Result<Url, Error> {
+    if s.starts_with(IPC_PREFIX) {
+        parse_ipc(&s[IPC_PREFIX.len()..])
+    } else if s.contains("://") {
+        parse_url(s)
+    } else if s.contains('/') || s.ends_with(".ipc") {
+        parse_ipc(s)
+    } else {
+        parse_socket(s)
+    }
+}
+
+/// Parse path to IPC socket into `ipc` URL with absolute path,
+/// relative path is resolved against current directory
+pub fn parse_ipc(s: &str) -> Result<Url, Error> {
+    if s.is_empty() {
+        return Err(Error::ExecError("Empty IPC socket path".to_string()));
+    }
+    let path = Path::new(s);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+
+    // `%` isn't in default set, but has to be escaped to decode path back
+    let escaped = path.to_string_lossy().replace('%', "%25");
+    let url = format!("{}{}", IPC_PREFIX, utf8_percent_encode(&escaped, DEFAULT_ENCODE_SET));
+    url.parse().map_err(Error::from)
+}
+
 /// Parse URL for ethereum node
 pub fn parse_url(s: &str) -> Result<Url, Error> {
     let addr: Url = s.parse()?;
+    if !UPSTREAM_SCHEMES.contains(&addr.scheme()) {
+        return Err(Error::ExecError(format!(
+            "Unsupported upstream scheme `{}`, expected one of: {}",
+            addr.scheme(),
+            UPSTREAM_SCHEMES.join(", ")
+        )));
+    }
+
     Ok(addr)
 }
 
-/// Parse socket address for ethereum node
+/// Parse `host:port` address for ethereum node.
+/// Plain HTTP is used for loopback hosts, HTTPS for others
 pub fn parse_socket(s: &str) -> Result<Url, Error> {
-    let addr = s
-        .parse::<SocketAddr>()
-        .map_err(Error::from)
-        .and_then(|a| format!("https://{}", a).parse().map_err(Error::from))?;
+    let invalid = || Error::ExecError(format!("Invalid upstream address: {}", s));
 
-    Ok(addr)
+    let (host, port) = match s.rfind(':') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => return Err(invalid()),
+    };
+    if host.is_empty() || host.contains('/') || port.parse::<u16>().is_err() {
+        return Err(invalid());
+    }
+
+    let scheme = if is_loopback(host) { "http" } else { "https" };
+    format!("{}://{}", scheme, s).parse().map_err(Error::from)
+}
+
+/// Check whether host points to local machine
+fn is_loopback(host: &str) -> bool {
+    host == "localhost" || host
+        .trim_matches(|c| c == '[' || c == ']')
+        .parse::<IpAddr>()
+        .map(|ip| ip.is_loopback())
+        .unwrap_or(false)
 }
 
 /// Request passphrase
 pub fn request_passphrase() -> Result<String, Error> {
-    println!("Enter passphrase: ");
+    eprintln!("Enter passphrase: ");
     let passphrase = rpassword::read_password().unwrap();
 
     Ok(passphrase)
@@ -222,6 +474,221 @@ pub fn request_passphrase() -> Result<String, Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use clap::{App, Arg};
+
+    fn matches(args: &[&str]) -> ArgMatches<'static> {
+        App::new("test")
+            .arg(
+                Arg::with_name("upstream")
+                    .long("upstream")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(Arg::with_name("quorum").long("quorum"))
+            .arg(Arg::with_name("timeout").long("timeout").takes_value(true))
+            .arg(Arg::with_name("retries").long("retries").takes_value(true))
+            .arg(
+                Arg::with_name("header")
+                    .long("header")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(Arg::with_name("security-level").long("security-level").takes_value(true))
+            .arg(Arg::with_name("host").long("host").takes_value(true))
+            .arg(Arg::with_name("port").long("port").takes_value(true))
+            .arg(Arg::with_name("block").long("block").takes_value(true))
+            .get_matches_from(args.to_vec())
+    }
+
+    #[test]
+    fn should_resolve_upstream_with_precedence() {
+        let env = EnvVars {
+            emerald_node: Some("https://node.example.com".to_string()),
+            ..EnvVars::default()
+        };
+
+        let rpc = get_upstream(&matches(&["test", "--upstream", "10.0.0.1:8545"]), &env).unwrap();
+        assert_eq!(rpc.urls, vec![parse_socket("10.0.0.1:8545").unwrap()]);
+
+        let rpc = get_upstream(&matches(&["test"]), &env).unwrap();
+        assert_eq!(rpc.urls, vec![Url::parse("https://node.example.com").unwrap()]);
+
+        let rpc = get_upstream(&matches(&["test"]), &EnvVars::default()).unwrap();
+        assert_eq!(rpc.urls, vec![parse_socket(DEFAULT_UPSTREAM).unwrap()]);
+    }
+
+    #[test]
+    fn should_parse_multiple_upstreams() {
+        let rpc = get_upstream(
+            &matches(&[
+                "test",
+                "--upstream",
+                "https://a.example.com",
+                "--upstream",
+                "https://b.example.com,https://c.example.com",
+                "--quorum",
+            ]),
+            &EnvVars::default(),
+        ).unwrap();
+        assert_eq!(rpc.urls.len(), 3);
+        assert_eq!(rpc.urls[2], Url::parse("https://c.example.com").unwrap());
+        assert!(rpc.quorum);
+
+        let env = EnvVars {
+            emerald_node: Some("https://a.example.com, https://b.example.com".to_string()),
+            ..EnvVars::default()
+        };
+        let rpc = get_upstream(&matches(&["test"]), &env).unwrap();
+        assert_eq!(rpc.urls.len(), 2);
+        assert!(!rpc.quorum);
+
+        let empty = matches(&["test", "--upstream", " , "]);
+        assert!(get_upstream(&empty, &EnvVars::default()).is_err());
+    }
+
+    #[test]
+    fn should_parse_header() {
+        assert_eq!(
+            parse_header("X-Api-Key: abc:def").unwrap(),
+            ("X-Api-Key".to_string(), "abc:def".to_string())
+        );
+        assert!(parse_header("X-Api-Key").is_err());
+        assert!(parse_header(": value").is_err());
+    }
+
+    #[test]
+    fn should_parse_block() {
+        assert_eq!(get_block(&matches(&["test"])).unwrap(), "latest");
+        assert_eq!(
+            get_block(&matches(&["test", "--block", "pending"])).unwrap(),
+            "pending"
+        );
+        assert_eq!(
+            get_block(&matches(&["test", "--block", "1000"])).unwrap(),
+            "0x3e8"
+        );
+        assert_eq!(
+            get_block(&matches(&["test", "--block", "0x03e8"])).unwrap(),
+            "0x3e8"
+        );
+        assert_eq!(
+            get_block(&matches(&["test", "--block", "earliest"])).unwrap(),
+            "earliest"
+        );
+        let hash = "0xB903239F8543D04B5DC1BA6579132B143087C68DB1B2168786408FCBCE568238";
+        assert_eq!(
+            get_block(&matches(&["test", "--block", hash])).unwrap(),
+            hash.to_lowercase()
+        );
+        assert!(get_block(&matches(&["test", "--block", &hash[..65]])).is_err());
+        assert!(get_block(&matches(&["test", "--block", "earlier"])).is_err());
+    }
+
+    #[test]
+    fn should_resolve_http_options() {
+        let env = EnvVars {
+            emerald_timeout: Some("5".to_string()),
+            emerald_retries: Some("1".to_string()),
+            emerald_headers: Some("X-A: 1; X-B: 2".to_string()),
+            ..EnvVars::default()
+        };
+
+        let opts = get_http_options(&matches(&["test"]), &env).unwrap();
+        assert_eq!(opts.timeout, Duration::from_secs(5));
+        assert_eq!(opts.retries, 1);
+        assert_eq!(opts.headers.len(), 2);
+        assert_eq!(opts.headers[1], ("X-B".to_string(), "2".to_string()));
+
+        let opts = get_http_options(
+            &matches(&["test", "--retries", "0", "--header", "X-C: 3"]),
+            &env,
+        ).unwrap();
+        assert_eq!(opts.retries, 0);
+        assert_eq!(opts.headers, vec![("X-C".to_string(), "3".to_string())]);
+
+        let opts = get_http_options(&matches(&["test"]), &EnvVars::default()).unwrap();
+        assert_eq!(opts, HttpOptions::default());
+    }
+
+    #[test]
+    fn should_keep_semicolons_in_header_values() {
+        let env = EnvVars {
+            emerald_headers: Some("Cookie: a=1; b=2; X-B: 2".to_string()),
+            ..EnvVars::default()
+        };
+
+        let opts = get_http_options(&matches(&["test"]), &env).unwrap();
+        assert_eq!(
+            opts.headers,
+            vec![
+                ("Cookie".to_string(), "a=1; b=2".to_string()),
+                ("X-B".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_resolve_server_addr_with_precedence() {
+        let env = EnvVars {
+            emerald_host: Some("0.0.0.0".to_string()),
+            emerald_port: Some("2020".to_string()),
+            ..EnvVars::default()
+        };
+
+        assert_eq!(
+            get_server_addr(&matches(&["test", "--port", "3030"]), &env).unwrap(),
+            "0.0.0.0:3030".parse::<SocketAddr>().unwrap()
+        );
+        assert_eq!(
+            get_server_addr(&matches(&["test"]), &EnvVars::default()).unwrap(),
+            "127.0.0.1:1920".parse::<SocketAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn should_resolve_security_level_with_precedence() {
+        let env = EnvVars {
+            emerald_security_level: Some("high".to_string()),
+            ..EnvVars::default()
+        };
+
+        assert_eq!(
+            get_security_lvl(&matches(&["test", "--security-level", "ultra"]), &env)
+                .unwrap()
+                .to_string(),
+            KdfDepthLevel::from_str("ultra").unwrap().to_string()
+        );
+        assert_eq!(
+            get_security_lvl(&matches(&["test"]), &env)
+                .unwrap()
+                .to_string(),
+            KdfDepthLevel::from_str("high").unwrap().to_string()
+        );
+        assert_eq!(
+            get_security_lvl(&matches(&["test"]), &EnvVars::default())
+                .unwrap()
+                .to_string(),
+            KdfDepthLevel::default().to_string()
+        );
+    }
+
+    #[test]
+    fn should_prefer_env_over_profile() {
+        let mut env = EnvVars {
+            emerald_node: Some("https://env.example.com".to_string()),
+            ..EnvVars::default()
+        };
+        env.merge_profile(&Profile {
+            upstream: Some("https://profile.example.com".to_string()),
+            gas: Some("0x5208".to_string()),
+            ..Profile::default()
+        });
+
+        assert_eq!(env.emerald_node, Some("https://env.example.com".to_string()));
+        assert_eq!(env.emerald_gas, Some("0x5208".to_string()));
+    }
 
     #[test]
     fn should_convert_hex_to_32bytes() {
@@ -282,11 +749,62 @@ mod tests {
     fn should_parse_socket_addr() {
         assert_eq!(
             parse_socket("127.0.0.1:8545").unwrap(),
-            Url::parse("https://127.0.0.1:8545").unwrap()
+            Url::parse("http://127.0.0.1:8545").unwrap()
+        );
+        assert_eq!(
+            parse_socket("localhost:8545").unwrap(),
+            Url::parse("http://localhost:8545").unwrap()
+        );
+        assert_eq!(
+            parse_socket("[::1]:8545").unwrap(),
+            Url::parse("http://[::1]:8545").unwrap()
+        );
+        assert_eq!(
+            parse_socket("10.0.0.1:8545").unwrap(),
+            Url::parse("https://10.0.0.1:8545").unwrap()
+        );
+        assert_eq!(
+            parse_socket("node.example.com:8545").unwrap(),
+            Url::parse("https://node.example.com:8545").unwrap()
         );
 
         assert!(parse_socket(";akjf.com").is_err());
         assert!(parse_socket("https://127.0.0.1:8545").is_err());
+        assert!(parse_socket("127.0.0.1:port").is_err());
+    }
+
+    #[test]
+    fn should_parse_upstream_schemes() {
+        for u in &[
+            "http://127.0.0.1:8545",
+            "https://node.example.com",
+            "ws://127.0.0.1:8546",
+            "wss://node.example.com/ws",
+            "ipc:///home/user/.ethereum/geth.ipc",
+        ] {
+            assert_eq!(parse_upstream(u).unwrap(), Url::parse(u).unwrap());
+        }
+
+        let cwd = env::current_dir().unwrap();
+        let relative = Url::parse(&format!("ipc://{}/geth.ipc", cwd.display())).unwrap();
+        assert_eq!(parse_upstream("ipc://geth.ipc").unwrap(), relative);
+        assert_eq!(parse_upstream("geth.ipc").unwrap(), relative);
+        assert_eq!(
+            parse_upstream("/home/user/.ethereum/geth.ipc").unwrap(),
+            Url::parse("ipc:///home/user/.ethereum/geth.ipc").unwrap()
+        );
+        assert_eq!(
+            parse_upstream("/tmp/my node/50%.ipc").unwrap().as_str(),
+            "ipc:///tmp/my%20node/50%25.ipc"
+        );
+        assert!(parse_upstream("ipc://").is_err());
+
+        assert_eq!(
+            parse_upstream("127.0.0.1:8545").unwrap(),
+            Url::parse("http://127.0.0.1:8545").unwrap()
+        );
+        assert!(parse_upstream("ftp://node.example.com").is_err());
+        assert!(parse_url("localhost:8545").is_err());
     }
 
     #[test]
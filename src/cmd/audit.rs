@@ -0,0 +1,89 @@
+//! # Audit log related subcommands
+
+use super::{EnvVars, Error, ExecResult};
+use audit::AuditLog;
+use chrono::NaiveDateTime;
+use clap::ArgMatches;
+use serde_json;
+
+/// Audit log commands
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+/// * audit - audit log
+///
+pub fn audit_cmd(matches: &ArgMatches, env: &EnvVars, audit: &AuditLog) -> ExecResult {
+    match matches.subcommand() {
+        ("show", Some(sub_m)) => show(sub_m, env, audit),
+        ("verify", Some(_)) => verify(audit),
+        _ => Err(Error::ExecError(
+            "Invalid audit subcommand. Use `emerald audit -h` for help".to_string(),
+        )),
+    }
+}
+
+/// Display audit log entries
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+/// * audit - audit log
+///
+fn show(matches: &ArgMatches, env: &EnvVars, audit: &AuditLog) -> ExecResult {
+    let mut entries = audit.entries()?;
+    if let Some(l) = matches.value_of("limit") {
+        let limit = l.parse::<usize>()?;
+        let skip = entries.len().saturating_sub(limit);
+        entries = entries.split_off(skip);
+    }
+
+    if matches.is_present("json") || env.is_json_output() {
+        for e in entries {
+            println!("{}", serde_json::to_string(&e)?);
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{0: <6} {1: <20} {2: <20} {3: <10} {4: <43} {5}",
+        "SEQ", "TIME", "OPERATION", "CHAIN", "ADDRESS", "OUTCOME"
+    );
+    for e in entries {
+        println!(
+            "{0: <6} {1: <20} {2: <20} {3: <10} {4: <43} {5}",
+            e.seq,
+            NaiveDateTime::from_timestamp(e.timestamp as i64, 0).format("%Y-%m-%d %H:%M:%S"),
+            e.operation,
+            e.chain,
+            e.address.unwrap_or_default(),
+            e.outcome,
+        );
+        if let Some(ref to) = e.to {
+            println!("{0: <6} to: {1}", "", to);
+        }
+        if let Some(ref value) = e.value {
+            println!("{0: <6} value: {1}", "", value);
+        }
+        if let Some(ref hash) = e.tx_hash {
+            println!("{0: <6} tx hash: {1}", "", hash);
+        }
+    }
+
+    Ok(())
+}
+
+/// Check integrity of audit log hash chain
+///
+/// # Arguments:
+///
+/// * audit - audit log
+///
+fn verify(audit: &AuditLog) -> ExecResult {
+    let count = audit.verify()?;
+    println!("Audit log is consistent, entries verified: {}", count);
+
+    Ok(())
+}
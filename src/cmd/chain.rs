@@ -0,0 +1,430 @@
+//! # Chain selection
+//!
+//! Besides built-in chains, custom chains can be defined in `chains.toml`
+//! inside base path:
+//!
+//! ```toml
+//! [[chain]]
+//! name = "mordor"
+//! id = 63
+//! upstream = "https://www.ethercluster.com/mordor"
+//! symbol = "METC"
+//! decimals = 18
+//! explorer = "https://blockscout.com/etc/mordor/tx/{tx}"
+//! eip1559 = false
+//! ```
+
+use super::signer::MAX_CHAIN_ID;
+use super::units::ETHER_DECIMALS;
+use super::{rpc, EnvVars, Error, ExecResult, DEFAULT_CHAIN_NAME};
+use clap::ArgMatches;
+use emerald::to_chain_id;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use toml;
+
+/// Chains filename inside base path
+pub const CHAINS_FILE: &str = "chains.toml";
+
+/// Native currency symbol of built-in chains
+const DEFAULT_SYMBOL: &str = "ETC";
+
+/// Placeholder for transaction hash in explorer URL template
+const EXPLORER_TX_PLACEHOLDER: &str = "{tx}";
+
+/// User-defined chain
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ChainDef {
+    pub name: String,
+    pub id: u64,
+    pub upstream: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<usize>,
+    pub explorer: Option<String>,
+    pub eip1559: Option<bool>,
+}
+
+impl ChainDef {
+    /// Whether chain is not known to `emerald-rs`
+    /// and requires own keystore
+    pub fn is_custom(&self) -> bool {
+        to_chain_id(&self.name).is_none()
+    }
+}
+
+/// Content of chains file
+#[derive(Debug, Default, Deserialize)]
+struct ChainsFile {
+    #[serde(default)]
+    chain: Vec<ChainDef>,
+}
+
+/// Read user-defined chains from base path, missing file means no chains
+///
+/// # Arguments:
+///
+/// * base_path - storage base path
+///
+pub fn load_chains(base_path: &Path) -> Result<Vec<ChainDef>, Error> {
+    let path = base_path.join(CHAINS_FILE);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut s = String::new();
+    File::open(&path).and_then(|mut f| f.read_to_string(&mut s))?;
+    let file = toml::from_str::<ChainsFile>(&s)?;
+
+    let mut names: Vec<&str> = file.chain.iter().map(|c| c.name.as_str()).collect();
+    names.sort();
+    if let Some(w) = names.windows(2).find(|w| w[0] == w[1]) {
+        return Err(Error::ExecError(format!(
+            "Chain `{}` is defined twice in {}",
+            w[0],
+            path.display()
+        )));
+    }
+
+    Ok(file.chain)
+}
+
+/// Selected chain
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chain {
+    /// Chain name, used to select storage
+    pub name: String,
+    /// Chain id, used for transaction signing
+    pub chain_id: Option<u64>,
+    /// Default upstream for chain
+    pub upstream: Option<String>,
+    /// Native currency symbol
+    pub symbol: String,
+    /// Native currency decimals
+    pub decimals: usize,
+    /// Explorer URL template, `{tx}` is replaced with transaction hash
+    pub explorer: Option<String>,
+    /// Whether new transactions are EIP-1559 dynamic-fee transactions by default
+    pub eip1559: bool,
+}
+
+impl Chain {
+    /// Resolve chain from command-line arguments and environment variables
+    ///
+    /// # Arguments:
+    ///
+    /// * matches - arguments supplied from command-line
+    /// * env - environment variables
+    /// * defs - user-defined chains
+    ///
+    pub fn resolve(matches: &ArgMatches, env: &EnvVars, defs: &[ChainDef]) -> Result<Chain, Error> {
+        let name = matches
+            .value_of("chain")
+            .or_else(|| env.emerald_chain.as_ref().map(String::as_str))
+            .unwrap_or(DEFAULT_CHAIN_NAME)
+            .to_string();
+        let def = defs.iter().find(|d| d.name == name);
+
+        let known_id = def
+            .map(|d| d.id)
+            .or_else(|| to_chain_id(&name).map(u64::from));
+        let env_id = match env.emerald_chain_id {
+            Some(ref id) => Some(id.parse::<u64>()?),
+            None => None,
+        };
+
+        // chain id from environment applies to chain selected in environment
+        // or to unknown chain, and must not silently change id of a known one
+        let chain_id = match matches.value_of("chain-id") {
+            Some(id) => Some(id.parse::<u64>()?),
+            None => match (env_id, known_id) {
+                (Some(e), Some(k)) if matches.is_present("chain") && e != k => {
+                    return Err(Error::ExecError(format!(
+                        "EMERALD_CHAIN_ID {} doesn't match id {} of chain `{}`, \
+                         use `--chain-id` to override",
+                        e, k, name
+                    )))
+                }
+                (Some(e), _) => Some(e),
+                (None, k) => k,
+            },
+        };
+        if let Some(id) = chain_id {
+            if id > MAX_CHAIN_ID {
+                return Err(Error::ExecError(format!(
+                    "Chain id {} is not supported, max is {}",
+                    id, MAX_CHAIN_ID
+                )));
+            }
+        }
+
+        Ok(Chain {
+            name,
+            chain_id,
+            upstream: def.and_then(|d| d.upstream.clone()),
+            symbol: def
+                .and_then(|d| d.symbol.clone())
+                .unwrap_or_else(|| DEFAULT_SYMBOL.to_string()),
+            decimals: def.and_then(|d| d.decimals).unwrap_or(ETHER_DECIMALS),
+            explorer: def.and_then(|d| d.explorer.clone()),
+            eip1559: def.and_then(|d| d.eip1559).unwrap_or(false),
+        })
+    }
+
+    /// Chain id for transaction signing
+    pub fn id(&self) -> Result<u64, Error> {
+        self.chain_id.ok_or_else(|| {
+            Error::ExecError(format!(
+                "Unknown chain id for `{}`, use `--chain-id`, `EMERALD_CHAIN_ID` \
+                 or define it in {}",
+                self.name, CHAINS_FILE
+            ))
+        })
+    }
+
+    /// Explorer link for transaction
+    ///
+    /// # Arguments:
+    ///
+    /// * tx_hash - transaction hash
+    ///
+    pub fn explorer_url(&self, tx_hash: &str) -> Option<String> {
+        self.explorer
+            .as_ref()
+            .map(|t| t.replace(EXPLORER_TX_PLACEHOLDER, tx_hash))
+    }
+
+    /// Compare chain id from another source with selected chain.
+    /// With `force` mismatch is only reported as warning
+    ///
+    /// # Arguments:
+    ///
+    /// * actual - chain id from `source`
+    /// * source - description of source for error message
+    /// * force - don't fail on mismatch
+    ///
+    pub fn check_id(&self, actual: u64, source: &str, force: bool) -> ExecResult {
+        let expected = self.id()?;
+        if expected == actual {
+            return Ok(());
+        }
+
+        let msg = format!(
+            "{} has chain id {}, but selected chain `{}` has id {}",
+            source, actual, self.name, expected
+        );
+        if force {
+            warn!("{}", msg);
+            Ok(())
+        } else {
+            Err(Error::ExecError(format!("{}. Use `--force` to ignore", msg)))
+        }
+    }
+
+    /// Check that remote nodes serve selected chain. Nodes without
+    /// `eth_chainId` are only reported as warning, any other failure
+    /// to get chain id fails the check
+    ///
+    /// # Arguments:
+    ///
+    /// * rpc - connector to remote nodes
+    /// * force - don't fail on mismatch or unknown chain id
+    ///
+    pub fn check_upstream(&self, rpc: &rpc::RpcConnector, force: bool) -> ExecResult {
+        for url in &rpc.urls {
+            let shown = rpc::hide_password(url);
+            match rpc::request_chain_id(&rpc.for_url(url)) {
+                Ok(Some(actual)) => self.check_id(actual, &format!("Upstream {}", shown), force)?,
+                Ok(None) => warn!(
+                    "Upstream {} doesn't support `eth_chainId`, chain isn't checked",
+                    shown
+                ),
+                Err(ref e) if force => warn!("Can't get chain id from {}: {}", shown, e),
+                Err(e) => {
+                    return Err(Error::ExecError(format!(
+                        "Can't get chain id from {}: {}. Use `--force` to ignore",
+                        shown, e
+                    )))
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    fn matches(args: &[&str]) -> ArgMatches<'static> {
+        App::new("test")
+            .arg(Arg::with_name("chain").long("chain").takes_value(true))
+            .arg(Arg::with_name("chain-id").long("chain-id").takes_value(true))
+            .get_matches_from(args.to_vec())
+    }
+
+    fn mordor() -> ChainDef {
+        ChainDef {
+            name: "mordor".to_string(),
+            id: 63,
+            upstream: Some("https://www.ethercluster.com/mordor".to_string()),
+            symbol: Some("METC".to_string()),
+            decimals: None,
+            explorer: Some("https://blockscout.com/etc/mordor/tx/{tx}".to_string()),
+            eip1559: None,
+        }
+    }
+
+    #[test]
+    fn should_use_default_chain() {
+        let chain = Chain::resolve(&matches(&["test"]), &EnvVars::default(), &[]).unwrap();
+        assert_eq!(chain.name, DEFAULT_CHAIN_NAME);
+        assert_eq!(chain.id().unwrap(), 61);
+        assert_eq!(chain.symbol, "ETC");
+        assert_eq!(chain.decimals, 18);
+        assert!(!chain.eip1559);
+    }
+
+    #[test]
+    fn should_prefer_cli_over_env() {
+        let env = EnvVars {
+            emerald_chain: Some("mainnet".to_string()),
+            emerald_chain_id: Some("62".to_string()),
+            ..EnvVars::default()
+        };
+
+        let chain = Chain::resolve(&matches(&["test", "--chain", "morden"]), &env, &[]).unwrap();
+        assert_eq!(chain.name, "morden");
+        assert_eq!(chain.id().unwrap(), 62);
+
+        let env = EnvVars {
+            emerald_chain_id: Some("61".to_string()),
+            ..env
+        };
+        let chain = Chain::resolve(
+            &matches(&["test", "--chain", "morden", "--chain-id", "62"]),
+            &env,
+            &[],
+        ).unwrap();
+        assert_eq!(chain.id().unwrap(), 62);
+    }
+
+    #[test]
+    fn should_fail_on_env_chain_id_mismatch() {
+        let env = EnvVars {
+            emerald_chain_id: Some("61".to_string()),
+            ..EnvVars::default()
+        };
+
+        assert!(Chain::resolve(&matches(&["test", "--chain", "morden"]), &env, &[]).is_err());
+
+        let chain = Chain::resolve(&matches(&["test", "--chain", "devnet"]), &env, &[]).unwrap();
+        assert_eq!(chain.id().unwrap(), 61);
+    }
+
+    #[test]
+    fn should_use_chain_id_from_env() {
+        let env = EnvVars {
+            emerald_chain: Some("morden".to_string()),
+            emerald_chain_id: Some("1337".to_string()),
+            ..EnvVars::default()
+        };
+
+        let chain = Chain::resolve(&matches(&["test"]), &env, &[]).unwrap();
+        assert_eq!(chain.name, "morden");
+        assert_eq!(chain.chain_id, Some(1337));
+    }
+
+    #[test]
+    fn should_reject_too_big_chain_id() {
+        let env = EnvVars::default();
+        let id = (MAX_CHAIN_ID + 1).to_string();
+        assert!(Chain::resolve(&matches(&["test", "--chain-id", &id]), &env, &[]).is_err());
+
+        let id = MAX_CHAIN_ID.to_string();
+        assert!(Chain::resolve(&matches(&["test", "--chain-id", &id]), &env, &[]).is_ok());
+    }
+
+    #[test]
+    fn should_fail_for_unknown_chain_without_id() {
+        let env = EnvVars::default();
+        let chain = Chain::resolve(&matches(&["test", "--chain", "devnet"]), &env, &[]).unwrap();
+        assert!(chain.id().is_err());
+    }
+
+    #[test]
+    fn should_use_custom_chain() {
+        let env = EnvVars::default();
+        let chain =
+            Chain::resolve(&matches(&["test", "--chain", "mordor"]), &env, &[mordor()]).unwrap();
+
+        assert_eq!(chain.id().unwrap(), 63);
+        assert_eq!(chain.symbol, "METC");
+        assert_eq!(chain.decimals, 18);
+        assert_eq!(
+            chain.upstream,
+            Some("https://www.ethercluster.com/mordor".to_string())
+        );
+        assert_eq!(
+            chain.explorer_url("0x01"),
+            Some("https://blockscout.com/etc/mordor/tx/0x01".to_string())
+        );
+        assert!(mordor().is_custom());
+    }
+
+    #[test]
+    fn should_check_chain_id() {
+        let env = EnvVars::default();
+        let chain = Chain::resolve(&matches(&["test"]), &env, &[]).unwrap();
+
+        assert!(chain.check_id(61, "Upstream", false).is_ok());
+        assert!(chain.check_id(62, "Upstream", false).is_err());
+        assert!(chain.check_id(62, "Upstream", true).is_ok());
+
+        let unknown =
+            Chain::resolve(&matches(&["test", "--chain", "devnet"]), &env, &[]).unwrap();
+        assert!(unknown.check_id(1, "Upstream", true).is_err());
+    }
+
+    #[test]
+    fn should_load_chains_file() {
+        let dir = TempDir::new("emerald").unwrap();
+        assert!(load_chains(dir.path()).unwrap().is_empty());
+
+        File::create(dir.path().join(CHAINS_FILE))
+            .and_then(|mut f| {
+                f.write_all(
+                    b"[[chain]]\nname = \"mordor\"\nid = 63\n\
+                      upstream = \"https://www.ethercluster.com/mordor\"\nsymbol = \"METC\"\n\
+                      explorer = \"https://blockscout.com/etc/mordor/tx/{tx}\"\n\n\
+                      [[chain]]\nname = \"dev\"\nid = 1337\neip1559 = true\n",
+                )
+            })
+            .unwrap();
+
+        let chains = load_chains(dir.path()).unwrap();
+        assert_eq!(chains.len(), 2);
+        assert_eq!(chains[0], mordor());
+        assert_eq!(chains[1].name, "dev");
+        assert_eq!(chains[1].id, 1337);
+        assert_eq!(chains[1].eip1559, Some(true));
+    }
+
+    #[test]
+    fn should_reject_duplicated_chains() {
+        let dir = TempDir::new("emerald").unwrap();
+        File::create(dir.path().join(CHAINS_FILE))
+            .and_then(|mut f| {
+                f.write_all(
+                    b"[[chain]]\nname = \"dev\"\nid = 1\n\n[[chain]]\nname = \"dev\"\nid = 2\n",
+                )
+            })
+            .unwrap();
+
+        assert!(load_chains(dir.path()).is_err());
+    }
+}
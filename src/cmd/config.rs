@@ -0,0 +1,397 @@
+//! # Configuration file with named profiles
+//!
+//! Values are resolved with precedence:
+//! command-line > environment variables > profile > built-in defaults
+
+use super::chain::Chain;
+use super::fee::DEFAULT_MAX_GAS_PRICE;
+use super::{rpc, EnvVars, Error, ExecResult, KdfDepthLevel, DEFAULT_CHAIN_NAME, DEFAULT_UPSTREAM};
+use clap::ArgMatches;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use toml;
+
+/// Config filename inside base path
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Named set of default values. Numeric values can be written
+/// either as TOML integers or as strings
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Profile {
+    pub chain: Option<String>,
+    pub upstream: Option<String>,
+    #[serde(default, deserialize_with = "string_or_int")]
+    pub gas: Option<String>,
+    #[serde(default, deserialize_with = "string_or_int")]
+    pub gas_price: Option<String>,
+    #[serde(default, deserialize_with = "string_or_int")]
+    pub max_gas_price: Option<String>,
+    pub security_level: Option<String>,
+    pub output: Option<String>,
+    #[serde(default, deserialize_with = "string_or_int")]
+    pub timeout: Option<String>,
+    #[serde(default, deserialize_with = "string_or_int")]
+    pub connect_timeout: Option<String>,
+    #[serde(default, deserialize_with = "string_or_int")]
+    pub retries: Option<String>,
+    pub proxy: Option<String>,
+    pub headers: Option<String>,
+}
+
+/// Profile value written as string or integer
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrInt {
+    Str(String),
+    Int(u64),
+}
+
+/// Deserialize optional value written as string or integer into string
+fn string_or_int<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        Option::<StringOrInt>::deserialize(deserializer)?.map(|v| match v {
+            StringOrInt::Str(s) => s,
+            StringOrInt::Int(n) => n.to_string(),
+        }),
+    )
+}
+
+/// Content of config file
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+/// Loaded configuration with selected profile
+#[derive(Debug, Default)]
+pub struct Config {
+    pub path: PathBuf,
+    pub profile_name: Option<String>,
+    pub profile: Profile,
+}
+
+impl Config {
+    /// Load config file and select profile
+    ///
+    /// # Arguments:
+    ///
+    /// * matches - arguments supplied from command-line
+    /// * env - environment variables
+    /// * base_path - storage base path
+    ///
+    pub fn load(matches: &ArgMatches, env: &EnvVars, base_path: &Path) -> Result<Config, Error> {
+        let (path, required) = match matches.value_of("config") {
+            Some(p) => (PathBuf::from(p), true),
+            None => (base_path.join(CONFIG_FILE), false),
+        };
+
+        let file = if path.exists() || required {
+            let mut s = String::new();
+            File::open(&path).and_then(|mut f| f.read_to_string(&mut s))?;
+            toml::from_str::<ConfigFile>(&s)?
+        } else {
+            ConfigFile::default()
+        };
+
+        let profile_name = matches
+            .value_of("profile")
+            .map(String::from)
+            .or_else(|| env.emerald_profile.clone())
+            .or(file.default_profile);
+        let profile = match profile_name {
+            Some(ref name) => file.profiles.get(name).cloned().ok_or_else(|| {
+                Error::ExecError(format!("Profile `{}` not found in {}", name, path.display()))
+            })?,
+            None => Profile::default(),
+        };
+
+        Ok(Config {
+            path,
+            profile_name,
+            profile,
+        })
+    }
+}
+
+/// Source of effective value
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Cli,
+    Env,
+    Profile,
+    Default,
+}
+
+/// Pick value according to precedence
+///
+/// # Arguments:
+///
+/// * cli - value from command-line
+/// * env - value from environment variable
+/// * profile - value from selected profile
+/// * default - built-in value
+///
+pub fn resolve(
+    cli: Option<&str>,
+    env: Option<&String>,
+    profile: Option<&String>,
+    default: Option<&str>,
+) -> Option<(String, Source)> {
+    cli.map(|v| (v.to_string(), Source::Cli))
+        .or_else(|| env.map(|v| (v.clone(), Source::Env)))
+        .or_else(|| profile.map(|v| (v.clone(), Source::Profile)))
+        .or_else(|| default.map(|v| (v.to_string(), Source::Default)))
+}
+
+/// Config commands
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line for `config`
+/// * global - top-level arguments
+/// * config - loaded config
+/// * chain - selected chain
+///
+pub fn config_cmd(
+    matches: &ArgMatches,
+    global: &ArgMatches,
+    config: &Config,
+    chain: &Chain,
+) -> ExecResult {
+    match matches.subcommand() {
+        ("show", Some(sub_m)) => show(sub_m, global, config, chain),
+        _ => Err(Error::ExecError(
+            "Invalid config subcommand. Use `emerald config -h` for help".to_string(),
+        )),
+    }
+}
+
+/// Print effective values and their sources. Command-line values are taken
+/// from arguments of `config show`, the same way as other commands do
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line for `config show`
+/// * global - top-level arguments
+/// * config - loaded config
+/// * chain - selected chain, may define default upstream
+///
+fn show(matches: &ArgMatches, global: &ArgMatches, config: &Config, chain: &Chain) -> ExecResult {
+    let env = EnvVars::parse();
+    let upstream = matches
+        .values_of("upstream")
+        .map(|vals| vals.collect::<Vec<_>>().join(","));
+    let p = &config.profile;
+    let sec_lvl = KdfDepthLevel::default().to_string();
+    let timeout = rpc::DEFAULT_TIMEOUT.to_string();
+    let connect_timeout = rpc::DEFAULT_CONNECT_TIMEOUT.to_string();
+    let retries = rpc::DEFAULT_RETRIES.to_string();
+    let max_gas_price = format!("0x{:x}", DEFAULT_MAX_GAS_PRICE);
+
+    let values = vec![
+        (
+            "chain",
+            resolve(
+                global.value_of("chain"),
+                env.emerald_chain.as_ref(),
+                p.chain.as_ref(),
+                Some(DEFAULT_CHAIN_NAME),
+            ),
+        ),
+        (
+            "upstream",
+            resolve(
+                upstream.as_ref().map(String::as_str),
+                env.emerald_node.as_ref(),
+                p.upstream.as_ref(),
+                Some(
+                    chain
+                        .upstream
+                        .as_ref()
+                        .map(String::as_str)
+                        .unwrap_or(DEFAULT_UPSTREAM),
+                ),
+            ),
+        ),
+        (
+            "gas",
+            resolve(
+                matches.value_of("gas"),
+                env.emerald_gas.as_ref(),
+                p.gas.as_ref(),
+                None,
+            ),
+        ),
+        (
+            "gas_price",
+            resolve(
+                matches.value_of("gas-price"),
+                env.emerald_gas_price.as_ref(),
+                p.gas_price.as_ref(),
+                None,
+            ),
+        ),
+        (
+            "max_gas_price",
+            resolve(
+                matches.value_of("max-gas-price"),
+                env.emerald_max_gas_price.as_ref(),
+                p.max_gas_price.as_ref(),
+                Some(&max_gas_price),
+            ),
+        ),
+        (
+            "security_level",
+            resolve(
+                matches.value_of("security-level"),
+                env.emerald_security_level.as_ref(),
+                p.security_level.as_ref(),
+                Some(&sec_lvl),
+            ),
+        ),
+        (
+            "output",
+            resolve(
+                None,
+                env.emerald_output.as_ref(),
+                p.output.as_ref(),
+                Some("table"),
+            ),
+        ),
+        (
+            "timeout",
+            resolve(
+                matches.value_of("timeout"),
+                env.emerald_timeout.as_ref(),
+                p.timeout.as_ref(),
+                Some(&timeout),
+            ),
+        ),
+        (
+            "connect_timeout",
+            resolve(
+                matches.value_of("connect-timeout"),
+                env.emerald_connect_timeout.as_ref(),
+                p.connect_timeout.as_ref(),
+                Some(&connect_timeout),
+            ),
+        ),
+        (
+            "retries",
+            resolve(
+                matches.value_of("retries"),
+                env.emerald_retries.as_ref(),
+                p.retries.as_ref(),
+                Some(&retries),
+            ),
+        ),
+        (
+            "proxy",
+            resolve(
+                matches.value_of("proxy"),
+                env.emerald_proxy.as_ref(),
+                p.proxy.as_ref(),
+                Some("(none)"),
+            ),
+        ),
+    ];
+
+    println!("Config file: {}", config.path.display());
+    println!(
+        "Profile:     {}",
+        config
+            .profile_name
+            .as_ref()
+            .map(String::as_str)
+            .unwrap_or("(none)")
+    );
+    println!();
+    println!("{0: <16} {1: <45} {2}", "NAME", "VALUE", "SOURCE");
+    for (name, v) in values {
+        let (value, source) = match v {
+            Some((value, source)) => (value, format!("{:?}", source).to_lowercase()),
+            None => ("(requested from node)".to_string(), "default".to_string()),
+        };
+        println!("{0: <16} {1: <45} {2}", name, value, source);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_resolve_with_precedence() {
+        let env = Some("env".to_string());
+        let profile = Some("profile".to_string());
+
+        assert_eq!(
+            resolve(Some("cli"), env.as_ref(), profile.as_ref(), Some("default")),
+            Some(("cli".to_string(), Source::Cli))
+        );
+        assert_eq!(
+            resolve(None, env.as_ref(), profile.as_ref(), Some("default")),
+            Some(("env".to_string(), Source::Env))
+        );
+        assert_eq!(
+            resolve(None, None, profile.as_ref(), Some("default")),
+            Some(("profile".to_string(), Source::Profile))
+        );
+        assert_eq!(
+            resolve(None, None, None, Some("default")),
+            Some(("default".to_string(), Source::Default))
+        );
+        assert_eq!(resolve(None, None, None, None), None);
+    }
+
+    #[test]
+    fn should_parse_profiles() {
+        let file: ConfigFile = toml::from_str(
+            r#"
+            default_profile = "dev"
+
+            [profiles.dev]
+            chain = "morden"
+            upstream = "http://127.0.0.1:8545"
+            gas_price = "0x04a817c800"
+            "#,
+        ).unwrap();
+
+        assert_eq!(file.default_profile, Some("dev".to_string()));
+        let dev = &file.profiles["dev"];
+        assert_eq!(dev.chain, Some("morden".to_string()));
+        assert_eq!(dev.gas, None);
+    }
+
+    #[test]
+    fn should_parse_integer_values() {
+        let file: ConfigFile = toml::from_str(
+            r#"
+            [profiles.dev]
+            gas = 21000
+            gas_price = "0x04a817c800"
+            timeout = 30
+            connect_timeout = "5"
+            retries = 3
+            "#,
+        ).unwrap();
+
+        let dev = &file.profiles["dev"];
+        assert_eq!(dev.gas, Some("21000".to_string()));
+        assert_eq!(dev.gas_price, Some("0x04a817c800".to_string()));
+        assert_eq!(dev.timeout, Some("30".to_string()));
+        assert_eq!(dev.connect_timeout, Some("5".to_string()));
+        assert_eq!(dev.retries, Some("3".to_string()));
+        assert_eq!(dev.max_gas_price, None);
+    }
+}
@@ -0,0 +1,573 @@
+//! # Contract registry and deployment related subcommands
+
+use super::abi::encode_args;
+use super::arg_handlers::*;
+use super::chain::Chain;
+use super::history::TxStatus;
+use super::rlp::{encode_bytes, encode_list, encode_u64};
+use super::signer::address_bytes;
+use super::storage::Storages;
+use super::transaction::{confirm_and_sign, fill_tx, mark_sent, remember};
+use super::{rpc, Address, EnvVars, Error, ExecResult, Transaction};
+use audit::{AuditLog, Event};
+use clap::ArgMatches;
+use emerald::{keccak256, trim_hex};
+use hex::{FromHex, ToHex};
+use serde_json::{self, Value};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time to wait for receipt of deployment transaction, in seconds
+const RECEIPT_TIMEOUT_SECS: u64 = 600;
+
+/// Interval between receipt requests, in seconds
+const RECEIPT_POLL_SECS: u64 = 5;
+
+/// Contract registry commands
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - keystores and local transaction data
+/// * env - environment variables
+/// * audit - audit log
+/// * chain - selected chain
+///
+pub fn contract_cmd(
+    matches: &ArgMatches,
+    storages: &Storages,
+    env: &EnvVars,
+    audit: &AuditLog,
+    chain: &Chain,
+) -> ExecResult {
+    let name = &chain.name;
+    match matches.subcommand() {
+        ("list", Some(sub_m)) => list(sub_m, storages, name),
+        ("import", Some(sub_m)) => import(sub_m, storages, name),
+        ("export", Some(sub_m)) => export(sub_m, storages, name),
+        ("hide", Some(sub_m)) => set_hidden(sub_m, storages, name, true),
+        ("unhide", Some(sub_m)) => set_hidden(sub_m, storages, name, false),
+        ("update", Some(sub_m)) => update(sub_m, storages, name),
+        ("deploy", Some(sub_m)) => deploy(sub_m, storages, env, audit, chain),
+        _ => Err(Error::ExecError(
+            "Invalid contract subcommand. Use `emerald contract -h` for help".to_string(),
+        )),
+    }
+}
+
+/// List all contracts
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+fn list(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    let show_hidden = matches.is_present("show-hidden");
+
+    println!("{0: <45} {1: <30} {2}", "ADDRESS", "NAME", "DESCRIPTION");
+    for c in list_contracts(storages, chain)? {
+        if is_hidden(&c) && !show_hidden {
+            continue;
+        }
+        println!(
+            "{0: <45} {1: <30} {2}",
+            str_field(&c, "address"),
+            str_field(&c, "name"),
+            str_field(&c, "description")
+        );
+    }
+
+    Ok(())
+}
+
+/// Import contract from `JSON` file. File should contain either
+/// a full contract description, or only an ABI array
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+fn import(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    let path = matches.value_of("path").expect("Required path to contract");
+    let json = read_json(path)?;
+
+    let mut contract = match json {
+        Value::Array(_) => json!({ "abi": json }),
+        Value::Object(_) => json,
+        _ => {
+            return Err(Error::ExecError(
+                "Expected contract description or ABI array".to_string(),
+            ))
+        }
+    };
+
+    if matches.is_present("address") {
+        let addr = get_address(matches, "address")?;
+        contract["address"] = Value::String(addr.to_string());
+    }
+    let address = contract_address(&contract)?;
+    if let Some(name) = matches.value_of("name") {
+        contract["name"] = Value::String(name.to_string());
+    }
+    if let Some(desc) = matches.value_of("description") {
+        contract["description"] = Value::String(desc.to_string());
+    }
+
+    put_contract(storages, chain, &contract)?;
+    println!("Imported contract: {}", &address);
+
+    Ok(())
+}
+
+/// Export contract into `JSON` file
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+fn export(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    let address = get_address(matches, "address")?;
+    let contract = get_contract(storages, chain, &address)?;
+
+    let mut path = PathBuf::from(matches.value_of("path").expect("Required export path"));
+    if path.is_dir() {
+        path.push(format!("{}.json", address));
+    }
+
+    let json = serde_json::to_string_pretty(&contract)?;
+    File::create(&path).and_then(|mut f| f.write_all(json.as_bytes()))?;
+
+    Ok(())
+}
+
+/// Toggle contract visibility for `list` operation
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+/// * hidden - new visibility flag
+///
+fn set_hidden(matches: &ArgMatches, storages: &Storages, chain: &str, hidden: bool) -> ExecResult {
+    let address = get_address(matches, "address")?;
+    let mut contract = get_contract(storages, chain, &address)?;
+    contract["hidden"] = Value::Bool(hidden);
+
+    put_contract(storages, chain, &contract)
+}
+
+/// Update `name`, `description` or ABI for existing contract
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+fn update(matches: &ArgMatches, storages: &Storages, chain: &str) -> ExecResult {
+    let address = get_address(matches, "address")?;
+    let mut contract = get_contract(storages, chain, &address)?;
+
+    if let Some(name) = matches.value_of("name") {
+        contract["name"] = Value::String(name.to_string());
+    }
+    if let Some(desc) = matches.value_of("description") {
+        contract["description"] = Value::String(desc.to_string());
+    }
+    if let Some(path) = matches.value_of("abi") {
+        contract["abi"] = read_json(path)?;
+    }
+
+    put_contract(storages, chain, &contract)
+}
+
+/// Deploy contract. Address of new contract is predicted from sender
+/// and nonce, with `--send` transaction is sent and deployed code is
+/// checked after the transaction is mined
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - keystores and local transaction data
+/// * env - environment variables
+/// * audit - audit log
+/// * chain - selected chain
+///
+fn deploy(
+    matches: &ArgMatches,
+    storages: &Storages,
+    env: &EnvVars,
+    audit: &AuditLog,
+    chain: &Chain,
+) -> ExecResult {
+    let from = get_address(matches, "from")?;
+    let path = matches.value_of("bytecode").expect("Required bytecode file");
+    let (mut data, artifact_abi) = read_bytecode(path)?;
+    let abi = match matches.value_of("abi") {
+        Some(path) => Some(read_json(path)?),
+        None => artifact_abi,
+    };
+    let args: Vec<&str> = matches
+        .values_of("args")
+        .map(|vals| vals.collect())
+        .unwrap_or_default();
+    if abi.is_some() || !args.is_empty() {
+        let types = constructor_types(abi.as_ref())?;
+        data.extend(encode_args(&types, &args)?);
+    }
+
+    let tr = Transaction {
+        nonce: 0,
+        gas_price: [0u8; 32],
+        gas_limit: 0,
+        to: None,
+        value: [0u8; 32],
+        data,
+    };
+    let nonces = storages.get_nonces(&chain.name);
+    let (tx, reserved) = fill_tx(matches, env, &nonces, chain, &from, tr)?;
+    let address = create_address(&from, tx.tr.nonce)?;
+    eprintln!("Contract address: {}", address);
+
+    let event = Event {
+        to: Some(address.to_string()),
+        ..Event::new("contract deploy", &chain.name)
+    };
+    let signed = confirm_and_sign(matches, storages, audit, chain, event, &from, &tx)?;
+    remember(storages, chain, &from, &tx, &signed, reserved)?;
+
+    if !matches.is_present("send") {
+        println!("{}", signed.to_hex());
+        return Ok(());
+    }
+
+    let rpc = get_upstream(matches, env)?;
+    chain.check_upstream(&rpc, matches.is_present("force"))?;
+    let hash = rpc::send_transaction(&rpc, &signed)?;
+    println!("Tx hash: {}", hash);
+    mark_sent(storages, chain, Some(&from), &signed)?;
+
+    let receipt = wait_receipt(&rpc, &hash)?;
+    let block = receipt["blockNumber"]
+        .as_str()
+        .and_then(|b| u64::from_str_radix(trim_hex(b), 16).ok());
+    let history = storages.get_history(&chain.name);
+    if receipt["status"].as_str() == Some("0x0") {
+        history.set_status(&hash, TxStatus::Failed, block)?;
+        return Err(Error::ExecError(format!(
+            "Contract deployment failed: transaction {} reverted",
+            hash
+        )));
+    }
+    history.set_status(&hash, TxStatus::Mined, block)?;
+
+    if rpc::request_code(&rpc, &address, "latest")?.is_empty() {
+        return Err(Error::ExecError(format!("No contract code at {}", address)));
+    }
+    println!("Contract deployed: {}", address);
+
+    if let Some(abi) = abi {
+        let contract = json!({ "address": address.to_string(), "abi": abi });
+        match put_contract(storages, &chain.name, &contract) {
+            Ok(_) => println!("Imported contract: {}", address),
+            Err(e) => warn!("Can't import contract into registry: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Address of contract created by sender with nonce
+///
+/// # Arguments:
+///
+/// * from - sender address
+/// * nonce - nonce of creation transaction
+///
+pub fn create_address(from: &Address, nonce: u64) -> Result<Address, Error> {
+    let rlp = encode_list(&[encode_bytes(&address_bytes(from)?), encode_u64(nonce)]);
+    let hash = keccak256(&rlp);
+    Address::from_str(&format!("0x{}", hash[12..].to_hex())).map_err(Error::from)
+}
+
+/// Read contract bytecode from file with hex, or from JSON artifact
+/// of compiler with `bytecode` field. ABI from artifact is returned too
+///
+/// # Arguments:
+///
+/// * path - path to bytecode file
+///
+fn read_bytecode<P: AsRef<Path>>(path: P) -> Result<(Vec<u8>, Option<Value>), Error> {
+    let mut s = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut s))?;
+
+    let (hex, abi) = match serde_json::from_str::<Value>(&s) {
+        Ok(Value::Object(artifact)) => {
+            let hex = match artifact.get("bytecode") {
+                Some(&Value::String(ref b)) => b.clone(),
+                Some(&Value::Object(ref b)) => b
+                    .get("object")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                _ => String::new(),
+            };
+            (hex, artifact.get("abi").cloned())
+        }
+        _ => (s.trim().to_string(), None),
+    };
+
+    let bytecode = Vec::from_hex(trim_hex(&hex))?;
+    if bytecode.is_empty() {
+        return Err(Error::ExecError("Empty contract bytecode".to_string()));
+    }
+    Ok((bytecode, abi))
+}
+
+/// Types of constructor inputs from contract ABI
+///
+/// # Arguments:
+///
+/// * abi - contract ABI
+///
+fn constructor_types(abi: Option<&Value>) -> Result<Vec<String>, Error> {
+    let abi = abi.ok_or_else(|| {
+        Error::ExecError("Contract ABI is required for constructor arguments".to_string())
+    })?;
+    let items = abi
+        .as_array()
+        .ok_or_else(|| Error::ExecError("Contract ABI should be an array".to_string()))?;
+
+    let inputs = items
+        .iter()
+        .find(|i| i["type"].as_str() == Some("constructor"))
+        .and_then(|c| c["inputs"].as_array())
+        .cloned()
+        .unwrap_or_default();
+    Ok(inputs
+        .iter()
+        .filter_map(|i| i["type"].as_str().map(String::from))
+        .collect())
+}
+
+/// Wait until transaction is mined
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * hash - transaction hash
+///
+fn wait_receipt(rpc: &rpc::RpcConnector, hash: &str) -> Result<Value, Error> {
+    let started = Instant::now();
+    info!("Waiting for transaction {} to be mined", hash);
+    loop {
+        if let Some(receipt) = rpc::request_receipt(rpc, hash)? {
+            return Ok(receipt);
+        }
+        if started.elapsed() > Duration::from_secs(RECEIPT_TIMEOUT_SECS) {
+            return Err(Error::ExecError(format!(
+                "Transaction {} is not mined after {} seconds, \
+                 check it later with `transaction status`",
+                hash, RECEIPT_TIMEOUT_SECS
+            )));
+        }
+        thread::sleep(Duration::from_secs(RECEIPT_POLL_SECS));
+    }
+}
+
+/// List all contracts for selected chain
+///
+/// # Arguments:
+///
+/// * storages - storages of all chains
+/// * chain - chain name
+///
+pub fn list_contracts(storages: &Storages, chain: &str) -> Result<Vec<Value>, Error> {
+    Ok(storages.get_contracts(chain)?.list())
+}
+
+/// Search contract by address
+///
+/// # Arguments:
+///
+/// * storages - storages of all chains
+/// * chain - chain name
+/// * addr - contract address
+///
+pub fn get_contract(storages: &Storages, chain: &str, addr: &Address) -> Result<Value, Error> {
+    list_contracts(storages, chain)?
+        .into_iter()
+        .find(|c| contract_address(c).ok().as_ref() == Some(addr))
+        .ok_or_else(|| Error::ExecError(format!("Contract not found: {}", addr)))
+}
+
+/// Store contract, existing entry for the same address is replaced.
+/// ABI is kept inline, so RPC methods and `export` get the full entry
+///
+/// # Arguments:
+///
+/// * storages - storages of all chains
+/// * chain - chain name
+/// * contract - contract description
+///
+pub fn put_contract(storages: &Storages, chain: &str, contract: &Value) -> ExecResult {
+    contract_address(contract)?;
+    if let Some(abi) = contract.get("abi") {
+        if !abi.is_array() {
+            return Err(Error::ExecError("Contract ABI should be an array".to_string()));
+        }
+    }
+
+    storages
+        .get_contracts(chain)?
+        .add(contract)
+        .map_err(|e| Error::ExecError(e.to_string()))
+}
+
+/// Extract address from contract description
+fn contract_address(contract: &Value) -> Result<Address, Error> {
+    contract
+        .get("address")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::ExecError("Required contract address".to_string()))
+        .and_then(|s| Address::from_str(s).map_err(Error::from))
+}
+
+/// Check whether contract is hidden from being listed
+fn is_hidden(contract: &Value) -> bool {
+    contract
+        .get("hidden")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Get string field of contract description, or empty string
+fn str_field<'a>(contract: &'a Value, name: &str) -> &'a str {
+    contract.get(name).and_then(Value::as_str).unwrap_or("")
+}
+
+/// Read `JSON` file
+fn read_json<P: AsRef<Path>>(path: P) -> Result<Value, Error> {
+    let mut s = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut s))?;
+    serde_json::from_str(&s).map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cmd::chain::ChainDef;
+    use tempdir::TempDir;
+
+    fn dev_storages(dir: &TempDir) -> Storages {
+        let dev = ChainDef {
+            name: "dev".to_string(),
+            id: 1337,
+            upstream: None,
+            symbol: None,
+            decimals: None,
+            explorer: None,
+            eip1559: None,
+        };
+        Storages::new(dir.path(), &[dev]).unwrap()
+    }
+
+    fn token_address() -> Address {
+        Address::from_str("0x085fb4f24031eaedbc2b611aa528f22343eb52db").unwrap()
+    }
+
+    #[test]
+    fn should_predict_contract_address() {
+        let from = Address::from_str("0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0").unwrap();
+        assert_eq!(
+            create_address(&from, 0).unwrap().to_string(),
+            "0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"
+        );
+        assert_eq!(
+            create_address(&from, 1).unwrap().to_string(),
+            "0x343c43a37d37dff08ae8c4a11544c718abb4fcf8"
+        );
+    }
+
+    #[test]
+    fn should_read_bytecode() {
+        let dir = TempDir::new("emerald").unwrap();
+        let hex = dir.path().join("contract.bin");
+        File::create(&hex)
+            .and_then(|mut f| f.write_all(b"0x6080604052\n"))
+            .unwrap();
+        assert_eq!(
+            read_bytecode(&hex).unwrap(),
+            (vec![0x60, 0x80, 0x60, 0x40, 0x52], None)
+        );
+
+        let artifact = dir.path().join("contract.json");
+        File::create(&artifact)
+            .and_then(|mut f| {
+                f.write_all(
+                    br#"{"abi": [{"type": "constructor", "inputs": [{"type": "uint256"}]}],
+                         "bytecode": {"object": "6080"}}"#,
+                )
+            })
+            .unwrap();
+        let (bytecode, abi) = read_bytecode(&artifact).unwrap();
+        assert_eq!(bytecode, vec![0x60, 0x80]);
+        assert_eq!(constructor_types(abi.as_ref()).unwrap(), vec!["uint256"]);
+
+        assert!(constructor_types(None).is_err());
+        assert!(constructor_types(Some(&json!([]))).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_store_abi_inline() {
+        let dir = TempDir::new("emerald").unwrap();
+        let storages = dev_storages(&dir);
+        let abi = json!([{"type": "function", "name": "totalSupply", "inputs": []}]);
+        let contract = json!({
+            "address": token_address().to_string(),
+            "name": "BitEther",
+            "abi": abi,
+        });
+
+        put_contract(&storages, "dev", &contract).unwrap();
+
+        let listed = list_contracts(&storages, "dev").unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0]["abi"], abi);
+
+        let stored = get_contract(&storages, "dev", &token_address()).unwrap();
+        assert_eq!(stored["name"], "BitEther");
+        assert_eq!(stored["abi"], abi);
+    }
+
+    #[test]
+    fn should_keep_abi_on_update() {
+        let dir = TempDir::new("emerald").unwrap();
+        let storages = dev_storages(&dir);
+        let abi = json!([{"type": "constructor", "inputs": []}]);
+        let contract = json!({"address": token_address().to_string(), "abi": abi});
+        put_contract(&storages, "dev", &contract).unwrap();
+
+        let mut stored = get_contract(&storages, "dev", &token_address()).unwrap();
+        stored["hidden"] = Value::Bool(true);
+        put_contract(&storages, "dev", &stored).unwrap();
+
+        let stored = get_contract(&storages, "dev", &token_address()).unwrap();
+        assert!(is_hidden(&stored));
+        assert_eq!(stored["abi"], abi);
+
+        let invalid = json!({"address": token_address().to_string(), "abi": {}});
+        assert!(put_contract(&storages, "dev", &invalid).is_err());
+    }
+}
@@ -10,6 +10,7 @@ use serde_json;
 use std::net::AddrParseError;
 use std::num;
 use std::{error, fmt, io, str, string};
+use toml;
 use url;
 
 macro_rules! from_err {
@@ -27,6 +28,8 @@ macro_rules! from_err {
 pub enum Error {
     /// Command execution error
     ExecError(String),
+    /// Error object returned by remote node: code and message
+    RpcError(i64, String),
 }
 
 impl From<AddrParseError> for Error {
@@ -49,11 +52,13 @@ from_err!(url::ParseError);
 from_err!(serde_json::Error);
 from_err!(hyper::error::Error);
 from_err!(http::uri::InvalidUri);
+from_err!(toml::de::Error);
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::ExecError(ref str) => write!(f, "Command execution error: {}", str),
+            Error::RpcError(code, ref msg) => write!(f, "Upstream error {}: {}", code, msg),
         }
     }
 }
@@ -0,0 +1,327 @@
+//! # Gas price suggestion from recent blocks
+//!
+//! Gas prices of transactions in recent blocks are sampled,
+//! and a percentile of them is suggested for selected level.
+//! For EIP-1559 transactions, base fee and priority fees
+//! are taken from `eth_feeHistory`
+
+use super::arg_handlers::hex_to_32bytes;
+use super::units::{add_u256, from_u64, mul_u64};
+use super::{rpc, EnvVars, Error, ExecResult};
+use clap::ArgMatches;
+use emerald::{to_even_str, trim_hex};
+use std::str::FromStr;
+
+/// Number of recent blocks to sample
+const FEE_BLOCKS: u64 = 20;
+
+/// Default max gas price, 1000 Gwei
+pub const DEFAULT_MAX_GAS_PRICE: u64 = 1_000_000_000_000;
+
+/// Priority fee if recent blocks have no transactions, 1 Gwei
+const DEFAULT_PRIORITY_FEE: u64 = 1_000_000_000;
+
+/// Level of suggested gas price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FeeLevel {
+    Slow,
+    Standard,
+    Fast,
+}
+
+impl FeeLevel {
+    /// Percentile of sampled gas prices
+    fn percentile(&self) -> usize {
+        match *self {
+            FeeLevel::Slow => 20,
+            FeeLevel::Standard => 50,
+            FeeLevel::Fast => 80,
+        }
+    }
+}
+
+impl FromStr for FeeLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slow" => Ok(FeeLevel::Slow),
+            "standard" => Ok(FeeLevel::Standard),
+            "fast" => Ok(FeeLevel::Fast),
+            _ => Err(Error::ExecError(format!("Unknown gas price level: {}", s))),
+        }
+    }
+}
+
+/// Suggest gas price for selected level from recent blocks.
+/// Node's `eth_gasPrice` is used if recent blocks have no transactions
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * level - gas price level
+///
+pub fn suggest_gas_price(rpc: &rpc::RpcConnector, level: FeeLevel) -> Result<[u8; 32], Error> {
+    let mut prices = sample_gas_prices(rpc)?;
+    if prices.is_empty() {
+        warn!("No transactions in recent blocks, node's gas price is used");
+        return parse_wei(&rpc::request_gas_price(rpc)?);
+    }
+
+    let price = percentile(&mut prices, level.percentile());
+    info!("Suggested {:?} gas price: {} wei", level, price);
+    Ok(from_u64(price))
+}
+
+/// Gas prices of transactions in recent blocks
+fn sample_gas_prices(rpc: &rpc::RpcConnector) -> Result<Vec<u64>, Error> {
+    let latest = rpc::request_block_number(rpc)?;
+    let first = latest.saturating_sub(FEE_BLOCKS - 1);
+    let numbers: Vec<u64> = (first..latest + 1).collect();
+
+    let mut prices = vec![];
+    for block in rpc::request_blocks(rpc, &numbers)? {
+        let txs = match block["transactions"].as_array() {
+            Some(txs) => txs,
+            None => continue,
+        };
+        for tx in txs.iter() {
+            if let Some(p) = tx["gasPrice"].as_str() {
+                prices.push(u64::from_str_radix(trim_hex(p), 16)?);
+            }
+        }
+    }
+
+    Ok(prices)
+}
+
+/// Fees suggested for EIP-1559 transaction
+#[derive(Debug, PartialEq)]
+pub struct FeeSuggestion {
+    /// Base fee of the next block
+    pub base_fee: [u8; 32],
+    /// Priority fee for selected level
+    pub priority_fee: [u8; 32],
+}
+
+impl FeeSuggestion {
+    /// Max fee per gas, twice the base fee leaves room for
+    /// base fee growth over several full blocks
+    ///
+    /// # Arguments:
+    ///
+    /// * priority_fee - max priority fee per gas
+    ///
+    pub fn max_fee(&self, priority_fee: &[u8; 32]) -> Result<[u8; 32], Error> {
+        add_u256(&mul_u64(&self.base_fee, 2)?, priority_fee)
+    }
+}
+
+/// Suggest EIP-1559 fees for selected level from fee history of recent blocks
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * level - priority fee level
+///
+pub fn suggest_fees(rpc: &rpc::RpcConnector, level: FeeLevel) -> Result<FeeSuggestion, Error> {
+    let history = rpc::request_fee_history(rpc, FEE_BLOCKS, level.percentile())?;
+
+    let base_fee = match history["baseFeePerGas"]
+        .as_array()
+        .and_then(|b| b.last())
+        .and_then(|b| b.as_str())
+    {
+        Some(b) => parse_wei(b)?,
+        None => {
+            return Err(Error::ExecError(
+                "No base fee in fee history, chain may not support EIP-1559".to_string(),
+            ))
+        }
+    };
+
+    let mut rewards = vec![];
+    if let Some(blocks) = history["reward"].as_array() {
+        for r in blocks.iter() {
+            if let Some(p) = r[0].as_str() {
+                rewards.push(u64::from_str_radix(trim_hex(p), 16)?);
+            }
+        }
+    }
+    let priority_fee = if rewards.is_empty() {
+        DEFAULT_PRIORITY_FEE
+    } else {
+        percentile(&mut rewards, 50)
+    };
+    info!("Suggested {:?} priority fee: {} wei", level, priority_fee);
+
+    Ok(FeeSuggestion {
+        base_fee,
+        priority_fee: from_u64(priority_fee),
+    })
+}
+
+/// Whether fee history is needed to complete fees of EIP-1559 transaction
+///
+/// # Arguments:
+///
+/// * max_fee - max fee per gas, in wei as hex
+/// * priority_fee - max priority fee per gas, in wei as hex or level
+///
+pub fn needs_fee_history(max_fee: Option<&str>, priority_fee: Option<&str>) -> bool {
+    max_fee.is_none() || priority_fee.map_or(true, |p| FeeLevel::from_str(p).is_ok())
+}
+
+/// Max fee and max priority fee of EIP-1559 transaction. Values missing in
+/// arguments are suggested from fee history, `standard` priority fee by default
+///
+/// # Arguments:
+///
+/// * max_fee - max fee per gas, in wei as hex
+/// * priority_fee - max priority fee per gas, in wei as hex or level
+/// * rpc - connector to remote node, required for suggested values
+///
+pub fn dynamic_fees(
+    max_fee: Option<&str>,
+    priority_fee: Option<&str>,
+    rpc: Option<&rpc::RpcConnector>,
+) -> Result<([u8; 32], [u8; 32]), Error> {
+    let level = match priority_fee {
+        Some(p) => FeeLevel::from_str(p).ok(),
+        None => Some(FeeLevel::Standard),
+    };
+    let suggestion = if needs_fee_history(max_fee, priority_fee) {
+        let rpc = rpc.ok_or_else(|| {
+            Error::ExecError("Upstream is required to suggest fees".to_string())
+        })?;
+        Some(suggest_fees(rpc, level.unwrap_or(FeeLevel::Standard))?)
+    } else {
+        None
+    };
+
+    let tip = match suggestion {
+        Some(ref s) if level.is_some() => s.priority_fee,
+        _ => parse_wei(priority_fee.expect("Expect priority fee"))?,
+    };
+    let max = match max_fee {
+        Some(f) => parse_wei(f)?,
+        None => suggestion.expect("Expect fee suggestion").max_fee(&tip)?,
+    };
+    if tip > max {
+        return Err(Error::ExecError(
+            "Max priority fee exceeds max fee".to_string(),
+        ));
+    }
+
+    Ok((max, tip))
+}
+
+/// Parse amount of wei from hex
+fn parse_wei(s: &str) -> Result<[u8; 32], Error> {
+    hex_to_32bytes(&to_even_str(trim_hex(s)))
+}
+
+/// Value at percentile of non-empty list
+///
+/// # Arguments:
+///
+/// * values - list of values, sorted in place
+/// * p - percentile, from 0 to 100
+///
+fn percentile(values: &mut [u64], p: usize) -> u64 {
+    values.sort();
+    values[(values.len() - 1) * p / 100]
+}
+
+/// Max gas price from arguments, environment vars or default value
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+///
+pub fn get_max_gas_price(matches: &ArgMatches, env: &EnvVars) -> Result<[u8; 32], Error> {
+    let max = matches
+        .value_of("max-gas-price")
+        .or_else(|| env.emerald_max_gas_price.as_ref().map(String::as_str));
+
+    match max {
+        Some(s) => parse_wei(s),
+        None => Ok(from_u64(DEFAULT_MAX_GAS_PRICE)),
+    }
+}
+
+/// Check gas price doesn't exceed the cap
+///
+/// # Arguments:
+///
+/// * gas_price - gas price of transaction
+/// * max - max gas price
+///
+pub fn check_gas_price(gas_price: &[u8; 32], max: &[u8; 32]) -> ExecResult {
+    if gas_price > max {
+        return Err(Error::ExecError(
+            "Gas price exceeds max gas price, \
+             use `--max-gas-price` to raise the limit"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_fee_level() {
+        assert_eq!(FeeLevel::from_str("slow").unwrap(), FeeLevel::Slow);
+        assert_eq!(FeeLevel::from_str("fast").unwrap(), FeeLevel::Fast);
+        assert!(FeeLevel::from_str("0x4a817c800").is_err());
+    }
+
+    #[test]
+    fn should_find_percentile() {
+        let mut values = vec![50, 10, 40, 20, 30];
+        assert_eq!(percentile(&mut values, 0), 10);
+        assert_eq!(percentile(&mut values, 50), 30);
+        assert_eq!(percentile(&mut values, 80), 40);
+        assert_eq!(percentile(&mut values, 100), 50);
+        assert_eq!(percentile(&mut [7], 20), 7);
+    }
+
+    #[test]
+    fn should_calculate_max_fee() {
+        let s = FeeSuggestion {
+            base_fee: from_u64(30_000_000_000),
+            priority_fee: from_u64(2_000_000_000),
+        };
+        assert_eq!(
+            s.max_fee(&s.priority_fee).unwrap(),
+            from_u64(62_000_000_000)
+        );
+    }
+
+    #[test]
+    fn should_use_fees_from_args() {
+        assert!(!needs_fee_history(Some("0xba43b7400"), Some("0x77359400")));
+        assert!(needs_fee_history(Some("0xba43b7400"), Some("fast")));
+        assert!(needs_fee_history(None, Some("0x77359400")));
+        assert!(needs_fee_history(Some("0xba43b7400"), None));
+
+        assert_eq!(
+            dynamic_fees(Some("0xba43b7400"), Some("0x77359400"), None).unwrap(),
+            (from_u64(50_000_000_000), from_u64(2_000_000_000))
+        );
+        assert!(dynamic_fees(Some("0x77359400"), Some("0xba43b7400"), None).is_err());
+        assert!(dynamic_fees(None, Some("0x77359400"), None).is_err());
+    }
+
+    #[test]
+    fn should_check_gas_price() {
+        let max = from_u64(DEFAULT_MAX_GAS_PRICE);
+        assert!(check_gas_price(&from_u64(20_000_000_000), &max).is_ok());
+        assert!(check_gas_price(&max, &max).is_ok());
+        assert!(check_gas_price(&from_u64(DEFAULT_MAX_GAS_PRICE + 1), &max).is_err());
+    }
+}
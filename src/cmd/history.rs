@@ -0,0 +1,315 @@
+//! # Journal of signed and sent transactions
+
+use super::signer::raw_tx_info;
+use super::storage::{lock_file, write_atomic};
+use super::Error;
+use audit::now;
+use emerald::{keccak256, trim_hex};
+use hex::ToHex;
+use serde_json;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// History filename inside chain directory
+const HISTORY_FILE: &str = "history.json";
+
+/// Transaction state, as known from the last check
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxStatus {
+    /// Signed, but not sent yet
+    Signed,
+    /// Sent and waiting in mempool
+    Pending,
+    /// Included into block and succeeded
+    Mined,
+    /// Included into block, but reverted
+    Failed,
+    /// Sent, but unknown to node anymore
+    Dropped,
+}
+
+impl TxStatus {
+    /// Whether transaction can still change its status
+    pub fn is_final(&self) -> bool {
+        match *self {
+            TxStatus::Mined | TxStatus::Failed => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for TxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            TxStatus::Signed => "signed",
+            TxStatus::Pending => "pending",
+            TxStatus::Mined => "mined",
+            TxStatus::Failed => "failed",
+            TxStatus::Dropped => "dropped",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Journal entry for single transaction
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TxRecord {
+    pub hash: String,
+    pub raw: String,
+    /// Sender, unknown for transactions signed elsewhere
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub value: String,
+    pub nonce: u64,
+    pub chain: String,
+    pub timestamp: u64,
+    pub status: TxStatus,
+    /// Block number for mined transaction
+    pub block: Option<u64>,
+}
+
+impl TxRecord {
+    /// Create record for signed raw transaction
+    ///
+    /// # Arguments:
+    ///
+    /// * raw - signed RLP-encoded transaction
+    /// * from - sender address, if known
+    /// * chain - chain name
+    /// * status - initial status
+    ///
+    pub fn new(
+        raw: &[u8],
+        from: Option<String>,
+        chain: &str,
+        status: TxStatus,
+    ) -> Result<TxRecord, Error> {
+        let info = raw_tx_info(raw)?;
+
+        Ok(TxRecord {
+            hash: tx_hash(raw),
+            raw: format!("0x{}", raw.to_hex()),
+            from,
+            to: info.to.map(|a| a.to_string()),
+            value: format!("0x{}", info.value.to_hex()),
+            nonce: info.nonce,
+            chain: chain.to_string(),
+            timestamp: now(),
+            status,
+            block: None,
+        })
+    }
+
+    /// Whether address is sender or receiver of transaction
+    pub fn involves(&self, addr: &str) -> bool {
+        let addr = addr.to_lowercase();
+        self.from.as_ref().map(|a| a.to_lowercase()) == Some(addr.clone())
+            || self.to.as_ref().map(|a| a.to_lowercase()) == Some(addr)
+    }
+}
+
+/// Hash of signed raw transaction
+pub fn tx_hash(raw: &[u8]) -> String {
+    format!("0x{}", keccak256(raw).to_hex())
+}
+
+/// Transaction hash in the form stored in journal: lowercase, `0x` prefixed
+pub fn normalize_hash(hash: &str) -> String {
+    format!("0x{}", trim_hex(hash.trim()).to_lowercase())
+}
+
+/// Local journal of transactions for selected chain
+pub struct TxHistory {
+    path: PathBuf,
+}
+
+impl TxHistory {
+    /// Create journal for selected chain
+    ///
+    /// # Arguments:
+    ///
+    /// * base_path - storage base path
+    /// * chain - chain name
+    ///
+    pub fn new<P: AsRef<Path>>(base_path: P, chain: &str) -> TxHistory {
+        TxHistory {
+            path: base_path.as_ref().join(chain).join(HISTORY_FILE),
+        }
+    }
+
+    /// Read all records, oldest first
+    pub fn list(&self) -> Result<Vec<TxRecord>, Error> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut json = String::new();
+        File::open(&self.path).and_then(|mut f| f.read_to_string(&mut json))?;
+        serde_json::from_str(&json).map_err(Error::from)
+    }
+
+    /// Search record by transaction hash
+    pub fn get(&self, hash: &str) -> Result<Option<TxRecord>, Error> {
+        let hash = normalize_hash(hash);
+        Ok(self.list()?.into_iter().find(|r| r.hash == hash))
+    }
+
+    /// Add new record, or replace existing one with the same hash
+    pub fn put(&self, record: TxRecord) -> Result<(), Error> {
+        self.update(|records| {
+            match records.iter().position(|r| r.hash == record.hash) {
+                Some(i) => records[i] = record,
+                None => records.push(record),
+            }
+            Ok(())
+        })
+    }
+
+    /// Update status of recorded transaction
+    ///
+    /// # Arguments:
+    ///
+    /// * hash - transaction hash
+    /// * status - new status
+    /// * block - block number for mined transaction
+    ///
+    pub fn set_status(
+        &self,
+        hash: &str,
+        status: TxStatus,
+        block: Option<u64>,
+    ) -> Result<(), Error> {
+        let hash = normalize_hash(hash);
+        self.update(
+            |records| match records.iter_mut().find(|r| r.hash == hash) {
+                Some(r) => {
+                    r.status = status;
+                    r.block = block;
+                    Ok(())
+                }
+                None => Err(Error::ExecError(format!("Unknown transaction: {}", hash))),
+            },
+        )
+    }
+
+    /// Modify records under journal lock, so concurrent runs
+    /// don't lose each other's changes. Nothing is saved if `f` fails
+    ///
+    /// # Arguments:
+    ///
+    /// * f - modifies list of records
+    ///
+    pub fn update<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Vec<TxRecord>) -> Result<(), Error>,
+    {
+        let _lock = lock_file(&self.path)?;
+        let mut records = self.list()?;
+        f(&mut records)?;
+        self.save(&records)
+    }
+
+    fn save(&self, records: &[TxRecord]) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(records)?;
+        write_atomic(&self.path, json.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+    use tempdir::TempDir;
+
+    /// Signed transaction from EIP-155
+    fn raw() -> Vec<u8> {
+        Vec::from_hex(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764\
+             00008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9\
+             d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+        ).unwrap()
+    }
+
+    #[test]
+    fn should_create_record_from_raw_tx() {
+        let r = TxRecord::new(&raw(), None, "mainnet", TxStatus::Pending).unwrap();
+
+        assert_eq!(
+            r.hash,
+            "0x33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788"
+        );
+        assert_eq!(r.nonce, 9);
+        assert_eq!(
+            r.to,
+            Some("0x3535353535353535353535353535353535353535".to_string())
+        );
+        assert!(r.involves("0x3535353535353535353535353535353535353535"));
+        assert!(!r.involves("0x0e7c045110b8dbf29765047380898919c5cb56f4"));
+    }
+
+    #[test]
+    fn should_store_records() {
+        let dir = TempDir::new("emerald").unwrap();
+        let history = TxHistory::new(dir.path(), "mainnet");
+        assert!(history.list().unwrap().is_empty());
+
+        let r = TxRecord::new(&raw(), None, "mainnet", TxStatus::Signed).unwrap();
+        history.put(r.clone()).unwrap();
+        history.put(r.clone()).unwrap();
+        assert_eq!(history.list().unwrap().len(), 1);
+
+        history.set_status(&r.hash, TxStatus::Mined, Some(100)).unwrap();
+        let stored = history.get(&r.hash).unwrap().unwrap();
+        assert_eq!(stored.status, TxStatus::Mined);
+        assert_eq!(stored.block, Some(100));
+
+        assert!(history.set_status("0x00", TxStatus::Mined, None).is_err());
+    }
+
+    #[test]
+    fn should_find_record_by_unprefixed_hash() {
+        let dir = TempDir::new("emerald").unwrap();
+        let history = TxHistory::new(dir.path(), "mainnet");
+        let r = TxRecord::new(&raw(), None, "mainnet", TxStatus::Pending).unwrap();
+        history.put(r.clone()).unwrap();
+
+        let hash = r.hash[2..].to_uppercase();
+        assert_eq!(normalize_hash(&hash), r.hash);
+        assert_eq!(history.get(&hash).unwrap(), Some(r.clone()));
+        history.set_status(&hash, TxStatus::Dropped, None).unwrap();
+        assert_eq!(
+            history.get(&r.hash).unwrap().unwrap().status,
+            TxStatus::Dropped
+        );
+    }
+
+    #[test]
+    fn should_keep_records_of_concurrent_writers() {
+        use std::thread;
+
+        let dir = TempDir::new("emerald").unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let path = dir.path().to_path_buf();
+                thread::spawn(move || {
+                    let history = TxHistory::new(&path, "mainnet");
+                    for j in 0..10 {
+                        let mut r =
+                            TxRecord::new(&raw(), None, "mainnet", TxStatus::Signed).unwrap();
+                        r.hash = format!("0x{:02x}{:02x}", i, j);
+                        history.put(r).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let history = TxHistory::new(dir.path(), "mainnet");
+        assert_eq!(history.list().unwrap().len(), 40);
+    }
+}
@@ -0,0 +1,373 @@
+//! # Keystore integrity check
+//!
+//! Every keyfile in keystore folder is validated: JSON structure,
+//! KDF and cipher params, and optionally that decrypted private key
+//! belongs to embedded address. Invalid keyfiles can be moved
+//! out of keystore to quarantine folder
+
+use super::{Error, KeyFile};
+use emerald::trim_hex;
+use hex::FromHex;
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Quarantine folder inside chain directory
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+/// Result of keyfile check
+#[derive(Debug, PartialEq)]
+pub enum KeyfileStatus {
+    /// Keyfile is well-formed
+    Valid,
+    /// Private key is decrypted and matches embedded address
+    Verified,
+    /// Private key can't be decrypted with provided passphrase
+    Locked,
+    /// Address is already used by keyfile with specified name
+    Duplicate(String),
+    /// Keyfile can't be used, with reason
+    Invalid(String),
+}
+
+impl KeyfileStatus {
+    /// Whether keyfile needs attention
+    pub fn is_problem(&self) -> bool {
+        match *self {
+            KeyfileStatus::Duplicate(_) | KeyfileStatus::Invalid(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Check result for a single file in keystore
+#[derive(Debug)]
+pub struct KeyfileCheck {
+    /// Name of file inside keystore folder
+    pub file: String,
+    /// Address embedded into keyfile
+    pub address: Option<String>,
+    /// Status of keyfile
+    pub status: KeyfileStatus,
+}
+
+/// Check all keyfiles in keystore folder, sorted by file name.
+/// Files not named as keyfiles are skipped
+///
+/// # Arguments:
+///
+/// * dir - keystore folder
+/// * passphrase - passphrase to decrypt private keys, if present
+///
+pub fn check_keystore(dir: &Path, passphrase: Option<&str>) -> Result<Vec<KeyfileCheck>, Error> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if !path.is_file() {
+            continue;
+        }
+        if is_keyfile_name(&name) {
+            paths.push(path);
+        } else {
+            info!("Skip unknown file in keystore: {}", name);
+        }
+    }
+    paths.sort();
+
+    let mut seen: HashMap<String, String> = HashMap::new();
+    let mut checks = vec![];
+    for path in paths {
+        let file = path
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (address, mut status) = check_keyfile(&path, passphrase);
+
+        if let Some(ref addr) = address {
+            if !status.is_problem() {
+                match seen.get(addr) {
+                    Some(first) => status = KeyfileStatus::Duplicate(first.clone()),
+                    None => {
+                        seen.insert(addr.clone(), file.clone());
+                    }
+                }
+            }
+        }
+
+        checks.push(KeyfileCheck {
+            file,
+            address,
+            status,
+        });
+    }
+
+    Ok(checks)
+}
+
+/// Whether file is named as keyfile: `UTC--` prefixed or `.json`
+fn is_keyfile_name(name: &str) -> bool {
+    name.starts_with("UTC--") || name.ends_with(".json")
+}
+
+/// Check single keyfile
+///
+/// # Arguments:
+///
+/// * path - path to keyfile
+/// * passphrase - passphrase to decrypt private key, if present
+///
+fn check_keyfile(path: &Path, passphrase: Option<&str>) -> (Option<String>, KeyfileStatus) {
+    let text = match fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) => return (None, KeyfileStatus::Invalid(format!("can't read: {}", e))),
+    };
+    let json: Value = match serde_json::from_str(&text) {
+        Ok(j) => j,
+        Err(e) => return (None, KeyfileStatus::Invalid(format!("invalid JSON: {}", e))),
+    };
+    let address = json["address"]
+        .as_str()
+        .map(|a| format!("0x{}", trim_hex(a)));
+
+    if let Err(e) = check_crypto(&json) {
+        return (address, KeyfileStatus::Invalid(e));
+    }
+    let kf = match KeyFile::decode(&text) {
+        Ok(kf) => kf,
+        Err(e) => {
+            return (
+                address,
+                KeyfileStatus::Invalid(format!("invalid keyfile: {}", e)),
+            )
+        }
+    };
+    let address = Some(kf.address.to_string());
+
+    let passphrase = match passphrase {
+        Some(p) if !is_hardware(&json) => p,
+        _ => return (address, KeyfileStatus::Valid),
+    };
+    (address, verify_status(&kf, passphrase))
+}
+
+/// Decrypt private key and check it belongs to keyfile address
+fn verify_status(kf: &KeyFile, passphrase: &str) -> KeyfileStatus {
+    match kf.decrypt_key(passphrase) {
+        Ok(pk) => match pk.to_address() {
+            Ok(ref a) if *a == kf.address => KeyfileStatus::Verified,
+            Ok(a) => KeyfileStatus::Invalid(format!("private key belongs to {}", a)),
+            Err(e) => KeyfileStatus::Invalid(format!("invalid private key: {}", e)),
+        },
+        Err(_) => KeyfileStatus::Locked,
+    }
+}
+
+/// Crypto section of keyfile, named `Crypto` by some old clients
+fn crypto_section(json: &Value) -> Option<&Value> {
+    json.get("crypto").or_else(|| json.get("Crypto"))
+}
+
+/// Whether keyfile refers to a key on hardware wallet
+fn is_hardware(json: &Value) -> bool {
+    crypto_section(json).map_or(false, |c| c["cipher"] == "hardware")
+}
+
+/// Validate KDF and cipher params of keyfile
+///
+/// # Arguments:
+///
+/// * json - keyfile JSON
+///
+pub fn check_crypto(json: &Value) -> Result<(), String> {
+    if json["version"] != 3 {
+        return Err("unsupported keyfile version".to_string());
+    }
+    let crypto = crypto_section(json).ok_or_else(|| "no crypto section".to_string())?;
+    if is_hardware(json) {
+        return Ok(());
+    }
+
+    match crypto["cipher"].as_str() {
+        Some("aes-128-ctr") => {}
+        Some(c) => return Err(format!("unsupported cipher `{}`", c)),
+        None => return Err("no cipher".to_string()),
+    }
+    check_hex(&crypto["cipherparams"]["iv"], Some(16), "cipher IV")?;
+    check_hex(&crypto["ciphertext"], Some(32), "ciphertext")?;
+    check_hex(&crypto["mac"], Some(32), "MAC")?;
+
+    let params = &crypto["kdfparams"];
+    if params["dklen"] != 32 {
+        return Err("invalid KDF `dklen`, expected 32".to_string());
+    }
+    check_hex(&params["salt"], None, "KDF salt")?;
+
+    match crypto["kdf"].as_str() {
+        Some("scrypt") => {
+            let n = params["n"].as_u64().unwrap_or(0);
+            if n < 2 || !n.is_power_of_two() {
+                return Err("invalid scrypt `n`, expected power of 2".to_string());
+            }
+            if params["r"].as_u64().unwrap_or(0) == 0 || params["p"].as_u64().unwrap_or(0) == 0 {
+                return Err("invalid scrypt `r` or `p`".to_string());
+            }
+        }
+        Some("pbkdf2") => {
+            if params["c"].as_u64().unwrap_or(0) == 0 {
+                return Err("invalid pbkdf2 iterations count `c`".to_string());
+            }
+            if params["prf"] != "hmac-sha256" {
+                return Err("unsupported pbkdf2 `prf`".to_string());
+            }
+        }
+        Some(k) => return Err(format!("unsupported KDF `{}`", k)),
+        None => return Err("no KDF".to_string()),
+    }
+
+    Ok(())
+}
+
+/// Check value is a hex string of expected length
+fn check_hex(value: &Value, len: Option<usize>, name: &str) -> Result<(), String> {
+    let bytes = value
+        .as_str()
+        .and_then(|s| Vec::<u8>::from_hex(trim_hex(s)).ok())
+        .ok_or_else(|| format!("invalid {}", name))?;
+
+    match len {
+        Some(l) if bytes.len() != l => Err(format!("invalid {} length", name)),
+        _ if bytes.is_empty() => Err(format!("empty {}", name)),
+        _ => Ok(()),
+    }
+}
+
+/// Move file from keystore to quarantine folder.
+/// A numeric suffix is added if quarantine already has a file with same name
+///
+/// # Arguments:
+///
+/// * dir - keystore folder
+/// * file - name of file inside keystore folder
+/// * quarantine - quarantine folder
+///
+pub fn quarantine(dir: &Path, file: &str, quarantine: &Path) -> Result<PathBuf, Error> {
+    fs::create_dir_all(quarantine)?;
+
+    let mut target = quarantine.join(file);
+    let mut n = 1;
+    while target.exists() {
+        target = quarantine.join(format!("{}.{}", file, n));
+        n += 1;
+    }
+    fs::rename(dir.join(file), &target)?;
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    /// PBKDF2 keyfile from Web3 Secret Storage test vectors
+    fn keyfile() -> Value {
+        json!({
+            "address": "008aeeda4d805471df9b2a5b0f38a0c3bcba786b",
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": {"iv": "6087dab2f9fdbbfaddc31a909735c1e6"},
+                "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                "kdf": "pbkdf2",
+                "kdfparams": {
+                    "c": 262144,
+                    "dklen": 32,
+                    "prf": "hmac-sha256",
+                    "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                },
+                "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+            },
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3
+        })
+    }
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        let mut f = File::create(dir.join(name)).unwrap();
+        f.write_all(content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn should_check_crypto_params() {
+        assert!(check_crypto(&keyfile()).is_ok());
+
+        let mut kf = keyfile();
+        kf["crypto"]["cipher"] = json!("aes-256-cbc");
+        assert!(check_crypto(&kf).is_err());
+
+        let mut kf = keyfile();
+        kf["crypto"]["kdfparams"]["dklen"] = json!(16);
+        assert!(check_crypto(&kf).is_err());
+
+        let mut kf = keyfile();
+        kf["crypto"]["mac"] = json!("517ead");
+        assert!(check_crypto(&kf).is_err());
+
+        let mut kf = keyfile();
+        kf["crypto"]["kdf"] = json!("scrypt");
+        assert!(check_crypto(&kf).is_err());
+        kf["crypto"]["kdfparams"] = json!({"n": 8192, "r": 8, "p": 1, "dklen": 32, "salt": "ab"});
+        assert!(check_crypto(&kf).is_ok());
+        kf["crypto"]["kdfparams"]["n"] = json!(1000);
+        assert!(check_crypto(&kf).is_err());
+    }
+
+    #[test]
+    fn should_find_invalid_and_duplicate_keyfiles() {
+        let tmp = TempDir::new("emerald").unwrap();
+        let dir = tmp.path();
+        let valid = keyfile().to_string();
+        write(dir, "a.json", &valid);
+        write(dir, "b.json", "{\"version\": 3, ");
+        write(dir, "c.json", &valid);
+        write(dir, "LOCK", "");
+        write(dir, "MANIFEST-000001", "{");
+        fs::create_dir(dir.join("sub")).unwrap();
+
+        let checks = check_keystore(dir, None).unwrap();
+        assert_eq!(checks.len(), 3);
+        assert_eq!(checks[0].status, KeyfileStatus::Valid);
+        assert_eq!(
+            checks[0].address,
+            Some("0x008aeeda4d805471df9b2a5b0f38a0c3bcba786b".to_string())
+        );
+        assert!(checks[1].status.is_problem());
+        assert_eq!(
+            checks[2].status,
+            KeyfileStatus::Duplicate("a.json".to_string())
+        );
+    }
+
+    #[test]
+    fn should_quarantine_file() {
+        let tmp = TempDir::new("emerald").unwrap();
+        let dir = tmp.path().join("keystore");
+        let q = tmp.path().join(QUARANTINE_DIR);
+        fs::create_dir(&dir).unwrap();
+        write(&dir, "broken.json", "{");
+
+        let target = quarantine(&dir, "broken.json", &q).unwrap();
+        assert_eq!(target, q.join("broken.json"));
+        assert!(!dir.join("broken.json").exists());
+
+        write(&dir, "broken.json", "{");
+        let target = quarantine(&dir, "broken.json", &q).unwrap();
+        assert_eq!(target, q.join("broken.json.1"));
+    }
+}
@@ -1,36 +1,60 @@
 //! # Execute command
 
+mod abi;
 mod account;
+mod address;
+mod audit;
+mod chain;
+mod config;
+mod contract;
 mod error;
+mod fee;
+mod history;
+mod integrity;
+mod nonce;
+mod rlp;
+mod server;
+mod signer;
+mod storage;
+mod token;
 mod transaction;
+mod units;
 #[macro_use]
 mod arg_handlers;
 
-use self::account::account_cmd;
+use self::account::{account_cmd, balances};
+use self::address::address_cmd;
 pub use self::arg_handlers::*;
+use self::audit::audit_cmd;
+use self::chain::{load_chains, Chain};
+use self::config::{config_cmd, Config};
+use self::contract::contract_cmd;
 pub use self::error::Error;
+use self::nonce::nonce_cmd;
+pub use self::storage::{lock_file, write_atomic};
+use self::storage::Storages;
+use self::token::{token_cmd, TokenList};
 use self::transaction::transaction_cmd;
 use super::emerald::keystore::{KdfDepthLevel, KeyFile};
 use super::emerald::mnemonic::{gen_entropy, Language, Mnemonic, ENTROPY_BYTE_LENGTH};
 use super::emerald::storage::{default_path, KeyfileStorage, StorageController};
 use super::emerald::PrivateKey;
 use super::emerald::{self, align_bytes, to_arr, to_even_str, trim_hex, Address, Transaction};
+use audit::{AuditLog, Event};
 use clap::ArgMatches;
 use rpc;
-use std::net::SocketAddr;
 use std::path::PathBuf;
 
 type ExecResult = Result<(), Error>;
 
 const DEFAULT_CHAIN_NAME: &str = "mainnet";
 const DEFAULT_UPSTREAM: &str = "127.0.0.1:8545";
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: &str = "1920";
 
 /// Create new command executor
 pub fn execute(matches: &ArgMatches) -> ExecResult {
-    let env = EnvVars::parse();
-
-    let chain = matches.value_of("chain").unwrap_or(DEFAULT_CHAIN_NAME);
-    info!("Chain name: {}", DEFAULT_CHAIN_NAME);
+    let mut env = EnvVars::parse();
 
     let mut base_path = PathBuf::new();
     if let Some(p) = matches
@@ -42,17 +66,34 @@ pub fn execute(matches: &ArgMatches) -> ExecResult {
         base_path = default_path();
     }
 
-    let storage_ctrl = StorageController::new(base_path)?;
+    let config = Config::load(matches, &env, &base_path)?;
+    env.merge_profile(&config.profile);
+
+    let chains = load_chains(&base_path)?;
+    let chain = Chain::resolve(matches, &env, &chains)?;
+    info!("Chain name: {}", &chain.name);
+    if env.emerald_node.is_none() {
+        env.emerald_node = chain.upstream.clone();
+    }
+
+    let audit = AuditLog::new(&base_path);
+    let tokens = TokenList::new(&base_path, &chain.name);
+    let storages = Storages::new(&base_path, &chains)?;
 
     match matches.subcommand() {
-        ("server", Some(sub_m)) => server_cmd(sub_m, storage_ctrl),
-        ("account", Some(sub_m)) => account_cmd(sub_m, storage_ctrl.get_keystore(chain)?, &env),
+        ("server", Some(sub_m)) => server_cmd(sub_m, storages.ctrl, &env, &audit, &chain.name),
+        ("account", Some(sub_m)) => account_cmd(sub_m, &storages, &env, &audit, &chain),
         ("transaction", Some(sub_m)) => {
-            transaction_cmd(sub_m, storage_ctrl.get_keystore(chain)?, &env, chain)
+            transaction_cmd(sub_m, &storages, &env, &audit, &chain)
         }
-        ("balance", Some(sub_m)) => balance_cmd(sub_m),
+        ("address", Some(sub_m)) => address_cmd(sub_m, &storages, &chain.name),
+        ("contract", Some(sub_m)) => contract_cmd(sub_m, &storages, &env, &audit, &chain),
+        ("token", Some(sub_m)) => token_cmd(sub_m, &storages, &env, &audit, &tokens, &chain),
+        ("audit", Some(sub_m)) => audit_cmd(sub_m, &env, &audit),
+        ("config", Some(sub_m)) => config_cmd(sub_m, matches, &config, &chain),
+        ("balance", Some(sub_m)) => balance_cmd(sub_m, &env, &storages, &chain),
         ("mnemonic", Some(_)) => mnemonic_cmd(),
-        ("nonce", Some(sub_m)) => nonce_cmd(sub_m),
+        ("nonce", Some(sub_m)) => nonce_cmd(sub_m, &env, &storages.get_nonces(&chain.name)),
         _ => Err(Error::ExecError(
             "No command selected. Use `-h` for help".to_string(),
         )),
@@ -65,33 +106,58 @@ pub fn execute(matches: &ArgMatches) -> ExecResult {
 ///
 /// * matches - arguments supplied from command-line
 /// * storage - `Keyfile` storage
+/// * env - environment variables
+/// * audit - audit log
 /// * chain - chain name
 ///
-fn server_cmd(matches: &ArgMatches, storage_ctrl: StorageController) -> ExecResult {
+fn server_cmd(
+    matches: &ArgMatches,
+    storage_ctrl: StorageController,
+    env: &EnvVars,
+    audit: &AuditLog,
+    chain: &str,
+) -> ExecResult {
     info!("Starting Emerald Vault - v{}", emerald::version());
-    let host = matches.value_of("host").unwrap_or_default();
-    let port = matches.value_of("port").unwrap_or_default();
-    let addr = format!("{}:{}", host, port).parse::<SocketAddr>()?;
-    let sec_lvl = get_security_lvl(matches)?;
+    let addr = get_server_addr(matches, env)?;
+    let sec_lvl = get_security_lvl(matches, env)?;
 
     info!("Security level set to '{}'", sec_lvl);
 
-    emerald::rpc::start(&addr, storage_ctrl, Some(sec_lvl));
+    audit.append(Event::new("server start", chain), "ok".to_string())?;
 
-    Ok(())
+    // key-using requests are recorded by front server
+    server::serve(
+        &addr,
+        |backend| emerald::rpc::start(backend, storage_ctrl, Some(sec_lvl)),
+        audit.clone(),
+        chain,
+    )
 }
 
-/// Show user balance
+/// Show user balance, or balances of all keystore accounts
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
+/// * env - environment variables
+/// * storages - keystores for built-in and custom chains
+/// * chain - selected chain
 ///
-fn balance_cmd(matches: &ArgMatches) -> ExecResult {
-    match get_upstream(matches) {
+fn balance_cmd(
+    matches: &ArgMatches,
+    env: &EnvVars,
+    storages: &Storages,
+    chain: &Chain,
+) -> ExecResult {
+    if matches.is_present("all") {
+        return balances(matches, storages.get_keystore(&chain.name)?, env, chain);
+    }
+
+    match get_upstream(matches, env) {
         Ok(ref rpc) => {
             let addr = get_address(matches, "address").expect("Required account address");
-            let balance = rpc::request_balance(rpc, &addr)?;
+            let block = get_block(matches)?;
+            let balance = rpc::request_balance(rpc, &addr, &block)?;
             info!("Balance for {} account", &addr);
             println!("{}", balance);
 
@@ -114,23 +180,3 @@ fn mnemonic_cmd() -> ExecResult {
     println!("{}", mn.sentence());
     Ok(())
 }
-
-/// Request `nonce` for specified account from a remote node
-///
-/// # Arguments:
-///
-/// * matches - arguments supplied from command-line
-///
-fn nonce_cmd(matches: &ArgMatches) -> ExecResult {
-    let addr = get_address(matches, "address").expect("Required account address");
-    let nonce = get_nonce(&matches, &addr)?;
-
-    info!("Nonce for {} account", &addr);
-    if matches.is_present("hex") {
-        println!("{:x}", nonce);
-    } else {
-        println!("{}", nonce);
-    }
-
-    Ok(())
-}
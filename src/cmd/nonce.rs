@@ -0,0 +1,412 @@
+//! # Local tracking of used nonces
+//!
+//! Nonces of signed transactions are recorded per chain and address,
+//! so successive transactions get consecutive nonces even before
+//! previous ones reach node's mempool
+
+use super::arg_handlers::*;
+use super::storage::{lock_file, write_atomic};
+use super::{rpc, Address, EnvVars, Error, ExecResult};
+use clap::ArgMatches;
+use emerald::trim_hex;
+use serde_json;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Nonces filename inside chain directory
+const NONCES_FILE: &str = "nonces.json";
+
+/// Used nonces, by address
+type UsedNonces = BTreeMap<String, Vec<u64>>;
+
+/// Persistent tracker of used nonces for selected chain.
+/// Nonces file is locked for every change, lock is never held
+/// while waiting for user
+#[derive(Clone)]
+pub struct NonceTracker {
+    path: PathBuf,
+}
+
+impl NonceTracker {
+    /// Create tracker for selected chain
+    ///
+    /// # Arguments:
+    ///
+    /// * base_path - storage base path
+    /// * chain - chain name
+    ///
+    pub fn new<P: AsRef<Path>>(base_path: P, chain: &str) -> NonceTracker {
+        NonceTracker {
+            path: base_path.as_ref().join(chain).join(NONCES_FILE),
+        }
+    }
+
+    /// Nonces used locally, but not counted by node yet
+    ///
+    /// # Arguments:
+    ///
+    /// * addr - sender address
+    /// * pending - nonce from node, including pending transactions
+    ///
+    pub fn unconfirmed(&self, addr: &Address, pending: u64) -> Result<Vec<u64>, Error> {
+        Ok(self
+            .load()?
+            .remove(&addr.to_string())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&n| n >= pending)
+            .collect())
+    }
+
+    /// Choose nonce for next transaction and record it at once, so
+    /// concurrent processes don't get the same nonce. Nonces already
+    /// counted by node are forgotten, so node's value is used once it
+    /// catches up. Reservation is released, unless it is kept after
+    /// transaction is signed
+    ///
+    /// # Arguments:
+    ///
+    /// * addr - sender address
+    /// * pending - nonce from node, including pending transactions
+    ///
+    pub fn reserve(&self, addr: &Address, pending: u64) -> Result<Reservation, Error> {
+        let _lock = lock_file(&self.path)?;
+        let mut all = self.load()?;
+        let nonce = {
+            let used = all.entry(addr.to_string()).or_insert_with(Vec::new);
+            used.retain(|&n| n >= pending);
+            let nonce = next_nonce(used, pending);
+            used.push(nonce);
+            nonce
+        };
+        self.save(&all)?;
+
+        Ok(Reservation {
+            tracker: self.clone(),
+            addr: *addr,
+            nonce,
+            kept: false,
+        })
+    }
+
+    /// Record nonce of signed transaction
+    ///
+    /// # Arguments:
+    ///
+    /// * addr - sender address
+    /// * nonce - transaction nonce
+    ///
+    pub fn record(&self, addr: &Address, nonce: u64) -> Result<(), Error> {
+        let _lock = lock_file(&self.path)?;
+        let mut all = self.load()?;
+        {
+            let used = all.entry(addr.to_string()).or_insert_with(Vec::new);
+            if !used.contains(&nonce) {
+                used.push(nonce);
+                used.sort();
+            }
+        }
+        self.save(&all)
+    }
+
+    /// Forget nonce of transaction which wasn't signed
+    ///
+    /// # Arguments:
+    ///
+    /// * addr - sender address
+    /// * nonce - transaction nonce
+    ///
+    fn release(&self, addr: &Address, nonce: u64) -> Result<(), Error> {
+        let _lock = lock_file(&self.path)?;
+        let mut all = self.load()?;
+        let key = addr.to_string();
+        let empty = match all.get_mut(&key) {
+            Some(used) => {
+                used.retain(|&n| n != nonce);
+                used.is_empty()
+            }
+            None => return Ok(()),
+        };
+        if empty {
+            all.remove(&key);
+        }
+        self.save(&all)
+    }
+
+    /// Forget used nonces for address, or for all addresses
+    ///
+    /// # Arguments:
+    ///
+    /// * addr - sender address, `None` for all addresses
+    ///
+    pub fn reset(&self, addr: Option<&Address>) -> Result<(), Error> {
+        let _lock = lock_file(&self.path)?;
+        let mut all = self.load()?;
+        match addr {
+            Some(a) => {
+                all.remove(&a.to_string());
+            }
+            None => all.clear(),
+        }
+        self.save(&all)
+    }
+
+    fn load(&self) -> Result<UsedNonces, Error> {
+        if !self.path.exists() {
+            return Ok(UsedNonces::new());
+        }
+
+        let mut json = String::new();
+        File::open(&self.path).and_then(|mut f| f.read_to_string(&mut json))?;
+        serde_json::from_str(&json).map_err(Error::from)
+    }
+
+    fn save(&self, all: &UsedNonces) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(all)?;
+        write_atomic(&self.path, json.as_bytes())
+    }
+}
+
+/// Nonce reserved for transaction being signed,
+/// released on drop unless kept
+pub struct Reservation {
+    tracker: NonceTracker,
+    addr: Address,
+    nonce: u64,
+    kept: bool,
+}
+
+impl Reservation {
+    /// Reserved nonce
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Keep nonce recorded, when transaction is signed
+    pub fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        if let Err(e) = self.tracker.release(&self.addr, self.nonce) {
+            warn!("Can't release nonce {} of {}: {}", self.nonce, self.addr, e);
+        }
+    }
+}
+
+/// Next nonce after locally used ones
+///
+/// # Arguments:
+///
+/// * used - sorted used nonces
+/// * pending - nonce from node, including pending transactions
+///
+fn next_nonce(used: &[u64], pending: u64) -> u64 {
+    match used.last() {
+        Some(&n) if n >= pending => n + 1,
+        _ => pending,
+    }
+}
+
+/// Nonces between node's value and the highest used one,
+/// which were not used. Transactions after a gap can't be mined
+///
+/// # Arguments:
+///
+/// * used - sorted used nonces
+/// * pending - nonce from node, including pending transactions
+///
+fn find_gaps(used: &[u64], pending: u64) -> Vec<u64> {
+    match used.last() {
+        Some(&max) if max >= pending => (pending..max).filter(|n| !used.contains(n)).collect(),
+        _ => vec![],
+    }
+}
+
+/// Nonce commands
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+/// * nonces - local nonce tracker
+///
+pub fn nonce_cmd(matches: &ArgMatches, env: &EnvVars, nonces: &NonceTracker) -> ExecResult {
+    match matches.subcommand() {
+        ("reset", Some(sub_m)) => reset(sub_m, nonces),
+        ("gaps", Some(sub_m)) => gaps(sub_m, env, nonces),
+        _ => show(matches, env),
+    }
+}
+
+/// Request `nonce` for specified account from a remote node
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+///
+fn show(matches: &ArgMatches, env: &EnvVars) -> ExecResult {
+    let addr = get_address(matches, "address").expect("Required account address");
+    let nonce = get_nonce(&matches, env, &addr)?;
+
+    info!("Nonce for {} account", &addr);
+    if matches.is_present("hex") {
+        println!("{:x}", nonce);
+    } else {
+        println!("{}", nonce);
+    }
+
+    Ok(())
+}
+
+/// Forget locally used nonces
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * nonces - local nonce tracker
+///
+fn reset(matches: &ArgMatches, nonces: &NonceTracker) -> ExecResult {
+    match matches.value_of("address") {
+        Some(_) => {
+            let addr = get_address(matches, "address")?;
+            nonces.reset(Some(&addr))?;
+            println!("Nonces reset for {}", addr);
+        }
+        None => {
+            nonces.reset(None)?;
+            println!("Nonces reset for all addresses");
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare locally used nonces with node's pending nonce
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+/// * nonces - local nonce tracker
+///
+fn gaps(matches: &ArgMatches, env: &EnvVars, nonces: &NonceTracker) -> ExecResult {
+    let addr = get_address(matches, "address")?;
+    let rpc = get_upstream(matches, env)?;
+    let pending = u64::from_str_radix(
+        trim_hex(&rpc::request_nonce(&rpc, &addr, "pending")?),
+        16,
+    )?;
+    let used = nonces.unconfirmed(&addr, pending)?;
+
+    println!("Node nonce:       {}", pending);
+    println!("Next local nonce: {}", next_nonce(&used, pending));
+    if !used.is_empty() {
+        println!("Not seen by node: {}", join(&used));
+    }
+
+    let gaps = find_gaps(&used, pending);
+    if gaps.is_empty() {
+        println!("No gaps");
+    } else {
+        println!("Gaps: {}", join(&gaps));
+    }
+
+    Ok(())
+}
+
+/// Comma-separated list of nonces
+fn join(nonces: &[u64]) -> String {
+    nonces
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::thread;
+    use tempdir::TempDir;
+
+    fn addr() -> Address {
+        Address::from_str("0x0e7c045110b8dbf29765047380898919c5cb56f4").unwrap()
+    }
+
+    #[test]
+    fn should_find_next_nonce() {
+        assert_eq!(next_nonce(&[], 5), 5);
+        assert_eq!(next_nonce(&[5, 6], 5), 7);
+        assert_eq!(next_nonce(&[3, 4], 5), 5);
+    }
+
+    #[test]
+    fn should_find_gaps() {
+        assert!(find_gaps(&[], 5).is_empty());
+        assert!(find_gaps(&[5, 6, 7], 5).is_empty());
+        assert_eq!(find_gaps(&[5, 8], 5), vec![6, 7]);
+        assert_eq!(find_gaps(&[7], 5), vec![5, 6]);
+    }
+
+    #[test]
+    fn should_track_used_nonces() {
+        let dir = TempDir::new("emerald").unwrap();
+        let nonces = NonceTracker::new(dir.path(), "mainnet");
+
+        nonces.reserve(&addr(), 3).unwrap().keep();
+        nonces.record(&addr(), 4).unwrap();
+        // node hasn't seen transactions yet
+        let next = nonces.reserve(&addr(), 3).unwrap();
+        assert_eq!(next.nonce(), 5);
+        assert_eq!(nonces.unconfirmed(&addr(), 3).unwrap(), vec![3, 4, 5]);
+
+        // transaction isn't signed, nonce is released
+        drop(next);
+        assert_eq!(nonces.unconfirmed(&addr(), 3).unwrap(), vec![3, 4]);
+
+        // node caught up, local nonces are forgotten
+        assert_eq!(nonces.reserve(&addr(), 6).unwrap().nonce(), 6);
+        assert!(nonces.unconfirmed(&addr(), 0).unwrap().is_empty());
+
+        nonces.record(&addr(), 6).unwrap();
+        nonces.reset(Some(&addr())).unwrap();
+        assert_eq!(nonces.reserve(&addr(), 6).unwrap().nonce(), 6);
+    }
+
+    #[test]
+    fn should_reserve_distinct_nonces_concurrently() {
+        let dir = TempDir::new("emerald").unwrap();
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let nonces = NonceTracker::new(dir.path(), "mainnet");
+                thread::spawn(move || {
+                    (0..10)
+                        .map(|_| {
+                            let r = nonces.reserve(&addr(), 0).unwrap();
+                            let n = r.nonce();
+                            r.keep();
+                            n
+                        })
+                        .collect::<Vec<u64>>()
+                })
+            })
+            .collect();
+
+        let mut all: Vec<u64> = workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect();
+        all.sort();
+        assert_eq!(all, (0..40).collect::<Vec<u64>>());
+    }
+}
@@ -0,0 +1,198 @@
+//! # Recursive Length Prefix encoding
+//! Refer [RLP](https://github.com/ethereum/wiki/wiki/RLP) for more info
+
+use super::Error;
+
+/// Strip leading zero bytes of big-endian number
+pub fn trim_zeros(b: &[u8]) -> &[u8] {
+    let first = b.iter().position(|x| *x != 0).unwrap_or_else(|| b.len());
+    &b[first..]
+}
+
+/// Big-endian bytes of `u64` without leading zeros
+pub fn u64_bytes(v: u64) -> Vec<u8> {
+    let mut buf = [0u8; 8];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (v >> (8 * (7 - i))) as u8;
+    }
+    trim_zeros(&buf).to_vec()
+}
+
+/// Length prefix for payload
+fn prefix(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        vec![offset + len as u8]
+    } else {
+        let len_bytes = u64_bytes(len as u64);
+        let mut res = vec![offset + 55 + len_bytes.len() as u8];
+        res.extend_from_slice(&len_bytes);
+        res
+    }
+}
+
+/// Encode byte string
+pub fn encode_bytes(b: &[u8]) -> Vec<u8> {
+    if b.len() == 1 && b[0] < 0x80 {
+        return b.to_vec();
+    }
+
+    let mut res = prefix(b.len(), 0x80);
+    res.extend_from_slice(b);
+    res
+}
+
+/// Encode unsigned integer
+pub fn encode_u64(v: u64) -> Vec<u8> {
+    encode_bytes(&u64_bytes(v))
+}
+
+/// Encode big-endian unsigned integer, leading zeros are stripped
+pub fn encode_uint(b: &[u8]) -> Vec<u8> {
+    encode_bytes(trim_zeros(b))
+}
+
+/// Encode list of already encoded items
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload: Vec<u8> = items.iter().flat_map(|i| i.iter().cloned()).collect();
+    let mut res = prefix(payload.len(), 0xc0);
+    res.extend_from_slice(&payload);
+    res
+}
+
+/// Convert big-endian bytes into `u64`
+pub fn to_u64(b: &[u8]) -> Result<u64, Error> {
+    if b.len() > 8 {
+        return Err(Error::ExecError("RLP: integer overflow".to_string()));
+    }
+    Ok(b.iter().fold(0u64, |acc, x| (acc << 8) | u64::from(*x)))
+}
+
+/// Decode header of item, returns payload offset and length
+fn decode_header(b: &[u8]) -> Result<(usize, usize), Error> {
+    let long = |len_of_len: usize| -> Result<(usize, usize), Error> {
+        if b.len() < 1 + len_of_len {
+            return Err(Error::ExecError("RLP: unexpected end of data".to_string()));
+        }
+        let len = to_u64(&b[1..1 + len_of_len])?;
+        // length comes from untrusted data, reject before any arithmetic
+        if len > b.len() as u64 {
+            return Err(Error::ExecError("RLP: unexpected end of data".to_string()));
+        }
+        Ok((1 + len_of_len, len as usize))
+    };
+
+    let (offset, len) = match b.first() {
+        None => return Err(Error::ExecError("RLP: empty data".to_string())),
+        Some(&x) if x < 0x80 => (0, 1),
+        Some(&x) if x < 0xb8 => (1, (x - 0x80) as usize),
+        Some(&x) if x < 0xc0 => long((x - 0xb7) as usize)?,
+        Some(&x) if x < 0xf8 => (1, (x - 0xc0) as usize),
+        Some(&x) => long((x - 0xf7) as usize)?,
+    };
+
+    match offset.checked_add(len) {
+        Some(end) if end <= b.len() => Ok((offset, len)),
+        _ => Err(Error::ExecError("RLP: unexpected end of data".to_string())),
+    }
+}
+
+/// Decode list into payloads of its items. Nested lists are returned
+/// as payload without header
+///
+/// # Arguments:
+///
+/// * b - encoded list
+///
+pub fn decode_list(b: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    match b.first() {
+        Some(&x) if x >= 0xc0 => (),
+        _ => return Err(Error::ExecError("RLP: expected list".to_string())),
+    }
+    let (offset, len) = decode_header(b)?;
+    let mut payload = &b[offset..offset + len];
+
+    let mut items = vec![];
+    while !payload.is_empty() {
+        let (offset, len) = decode_header(payload)?;
+        items.push(&payload[offset..offset + len]);
+        payload = &payload[offset + len..];
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_encode_bytes() {
+        assert_eq!(encode_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+        assert_eq!(encode_bytes(&[]), vec![0x80]);
+        assert_eq!(encode_bytes(&[0x0f]), vec![0x0f]);
+        assert_eq!(encode_bytes(&[0x80]), vec![0x81, 0x80]);
+
+        let long = [0x61u8; 56];
+        let encoded = encode_bytes(&long);
+        assert_eq!(&encoded[..2], &[0xb8, 56]);
+        assert_eq!(encoded.len(), 58);
+    }
+
+    #[test]
+    fn should_encode_integers() {
+        assert_eq!(encode_u64(0), vec![0x80]);
+        assert_eq!(encode_u64(15), vec![0x0f]);
+        assert_eq!(encode_u64(1024), vec![0x82, 0x04, 0x00]);
+        assert_eq!(encode_uint(&[0, 0, 0x04, 0x00]), vec![0x82, 0x04, 0x00]);
+    }
+
+    #[test]
+    fn should_encode_list() {
+        assert_eq!(
+            encode_list(&[encode_bytes(b"cat"), encode_bytes(b"dog")]),
+            vec![0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+        );
+        assert_eq!(encode_list(&[]), vec![0xc0]);
+    }
+
+    #[test]
+    fn should_decode_list() {
+        let encoded = encode_list(&[
+            encode_bytes(b"cat"),
+            encode_u64(1024),
+            encode_u64(0),
+            encode_bytes(&[0x61u8; 60]),
+            encode_list(&[encode_u64(1)]),
+        ]);
+        let items = decode_list(&encoded).unwrap();
+
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[0], &b"cat"[..]);
+        assert_eq!(to_u64(items[1]).unwrap(), 1024);
+        assert_eq!(to_u64(items[2]).unwrap(), 0);
+        assert_eq!(items[3], &[0x61u8; 60][..]);
+        assert_eq!(items[4], &[0x01][..]);
+    }
+
+    #[test]
+    fn should_fail_to_decode_truncated_list() {
+        let encoded = encode_list(&[encode_bytes(b"cat"), encode_bytes(b"dog")]);
+        assert!(decode_list(&encoded[..encoded.len() - 1]).is_err());
+        assert!(decode_list(&encode_bytes(b"cat")).is_err());
+    }
+
+    #[test]
+    fn should_fail_to_decode_huge_length() {
+        // list with 8 bytes length of `u64::MAX`
+        let mut encoded = vec![0xff];
+        encoded.extend_from_slice(&[0xff; 8]);
+        encoded.extend_from_slice(&[0x80; 4]);
+        assert!(decode_list(&encoded).is_err());
+
+        // item inside list with 8 bytes length of `u64::MAX`
+        let mut encoded = vec![0xca, 0xbf];
+        encoded.extend_from_slice(&[0xff; 8]);
+        encoded.push(0x80);
+        assert!(decode_list(&encoded).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! # RPC server with audit of key-using requests
+//!
+//! Requests are served by `emerald-rs` RPC server, which listens on
+//! a free local port. Front server on the requested address passes
+//! requests to it, and records every request that uses a private key
+//! into audit log before the response is returned
+
+use super::ExecResult;
+use audit::{AuditLog, Event};
+use emerald::{keccak256, trim_hex};
+use hex::{FromHex, ToHex};
+use hyper::header::CONTENT_TYPE;
+use hyper::rt::{self, Future, Stream};
+use hyper::service::service_fn;
+use hyper::{Body, Request, Response, Server, StatusCode};
+use reqwest::Client;
+use serde_json::{self, Value};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::thread;
+
+/// Methods which decrypt private keys
+const AUDITED_METHODS: &[&str] = &["emerald_signTransaction", "emerald_shakeAccount"];
+
+/// Front server, passes requests to `emerald-rs` server
+struct Proxy {
+    client: Client,
+    backend: String,
+    audit: AuditLog,
+    chain: String,
+}
+
+impl Proxy {
+    /// Pass request to backend and audit it with the response
+    fn handle(&self, body: &[u8]) -> Response<Body> {
+        let res = self
+            .client
+            .post(&self.backend)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_vec())
+            .send()
+            .and_then(|mut r| r.text());
+
+        match res {
+            Ok(text) => {
+                self.record(body, &text);
+                Response::builder()
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(text))
+                    .expect("Expect valid response")
+            }
+            Err(e) => {
+                error!("Can't pass request to RPC server: {}", e);
+                Response::builder()
+                    .status(StatusCode::BAD_GATEWAY)
+                    .body(Body::from(e.to_string()))
+                    .expect("Expect valid response")
+            }
+        }
+    }
+
+    /// Record key-using requests into audit log
+    fn record(&self, body: &[u8], response: &str) {
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+        let response: Value = serde_json::from_str(response).unwrap_or(Value::Null);
+
+        for (event, outcome) in audit_events(&request, &response, &self.chain) {
+            if let Err(e) = self.audit.append(event, outcome) {
+                error!("Can't record server request into audit log: {}", e);
+            }
+        }
+    }
+}
+
+/// Start RPC server, blocks until process is stopped
+///
+/// # Arguments:
+///
+/// * addr - listen address
+/// * start - starts `emerald-rs` server on provided local address, blocking
+/// * audit - audit log
+/// * chain - chain name, used for requests without chain
+///
+pub fn serve<F>(addr: &SocketAddr, start: F, audit: AuditLog, chain: &str) -> ExecResult
+where
+    F: FnOnce(&SocketAddr),
+{
+    let backend = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let proxy = Arc::new(Proxy {
+        client: Client::new(),
+        backend: format!("http://{}", backend),
+        audit,
+        chain: chain.to_string(),
+    });
+
+    let server = Server::try_bind(addr)?
+        .serve(move || {
+            let proxy = proxy.clone();
+            service_fn(move |req: Request<Body>| {
+                let proxy = proxy.clone();
+                req.into_body()
+                    .concat2()
+                    .map(move |body| proxy.handle(&body))
+            })
+        })
+        .map_err(|e| error!("RPC server failed: {}", e));
+    thread::spawn(move || rt::run(server));
+
+    start(&backend);
+
+    Ok(())
+}
+
+/// Audit entries for key-using requests of single or batch request
+///
+/// # Arguments:
+///
+/// * request - JSON RPC request
+/// * response - JSON RPC response, `null` if unknown
+/// * chain - chain name, used for requests without chain
+///
+fn audit_events(request: &Value, response: &Value, chain: &str) -> Vec<(Event, String)> {
+    let requests = match *request {
+        Value::Array(ref items) => items.iter().collect(),
+        ref single => vec![single],
+    };
+
+    requests
+        .into_iter()
+        .filter_map(|r| {
+            let method = r["method"].as_str()?;
+            if !AUDITED_METHODS.contains(&method) {
+                return None;
+            }
+            let res = find_response(response, &r["id"]);
+            Some(audit_event(method, &r["params"], res, chain))
+        })
+        .collect()
+}
+
+/// Audit entry for key-using request
+///
+/// # Arguments:
+///
+/// * method - JSON RPC method
+/// * params - request params
+/// * response - response to request, if found
+/// * chain - chain name, used for requests without chain
+///
+fn audit_event(
+    method: &str,
+    params: &Value,
+    response: Option<&Value>,
+    chain: &str,
+) -> (Event, String) {
+    let account = &params[0];
+    let str_param = |name: &str| account[name].as_str().map(String::from);
+
+    let mut event = Event {
+        address: str_param("from").or_else(|| str_param("address")),
+        to: str_param("to"),
+        value: str_param("value"),
+        ..Event::new(
+            &format!("server {}", method),
+            params[1]["chain"].as_str().unwrap_or(chain),
+        )
+    };
+
+    let outcome = match response {
+        Some(res) if !res["error"].is_null() => format!(
+            "failed: {}",
+            res["error"]["message"].as_str().unwrap_or("unknown error")
+        ),
+        Some(res) => {
+            let raw = res["result"]
+                .as_str()
+                .and_then(|r| Vec::from_hex(trim_hex(r)).ok());
+            if method == "emerald_signTransaction" {
+                event.tx_hash = raw.map(|r| format!("0x{}", keccak256(&r).to_hex()));
+            }
+            "ok".to_string()
+        }
+        None => "unknown: no response".to_string(),
+    };
+
+    (event, outcome)
+}
+
+/// Find response to request with id in batch response,
+/// single response is returned as is
+fn find_response<'a>(response: &'a Value, id: &Value) -> Option<&'a Value> {
+    match *response {
+        Value::Array(ref items) => items.iter().find(|r| r["id"] == *id),
+        Value::Object(_) => Some(response),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign_request(id: u64) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "emerald_signTransaction",
+            "params": [{
+                "from": "0xb60e8dd61c5d32be8058bb8eb970870f07233155",
+                "to": "0xd46e8dd67c5d32be8058bb8eb970870f07244567",
+                "value": "0x9184e72a",
+                "passphrase": "1234567890"
+            }, {"chain": "morden"}],
+            "id": id
+        })
+    }
+
+    #[test]
+    fn should_audit_sign_requests() {
+        let request = json!([
+            sign_request(1),
+            {"jsonrpc": "2.0", "method": "emerald_listAccounts", "params": [], "id": 2},
+            sign_request(3)
+        ]);
+        let response = json!([
+            {"jsonrpc": "2.0", "result": "0xf86c", "id": 1},
+            {"jsonrpc": "2.0", "result": [], "id": 2},
+            {"jsonrpc": "2.0", "error": {"code": -32000, "message": "Invalid passphrase"}, "id": 3}
+        ]);
+
+        let events = audit_events(&request, &response, "mainnet");
+        assert_eq!(events.len(), 2);
+
+        let (ref signed, ref outcome) = events[0];
+        assert_eq!(signed.operation, "server emerald_signTransaction");
+        assert_eq!(signed.chain, "morden");
+        assert_eq!(
+            signed.address,
+            Some("0xb60e8dd61c5d32be8058bb8eb970870f07233155".to_string())
+        );
+        assert_eq!(
+            signed.tx_hash,
+            Some(format!("0x{}", keccak256(&[0xf8, 0x6c]).to_hex()))
+        );
+        assert_eq!(outcome, "ok");
+        assert!(!format!("{:?}", signed).contains("1234567890"));
+
+        let (ref failed, ref outcome) = events[1];
+        assert!(failed.tx_hash.is_none());
+        assert_eq!(outcome, "failed: Invalid passphrase");
+    }
+
+    #[test]
+    fn should_audit_request_without_response() {
+        let mut request = sign_request(1);
+        request["params"] = json!([{"address": "0xb60e8dd61c5d32be8058bb8eb970870f07233155"}]);
+        request["method"] = json!("emerald_shakeAccount");
+
+        let events = audit_events(&request, &Value::Null, "mainnet");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0.chain, "mainnet");
+        assert!(events[0].0.address.is_some());
+        assert_eq!(events[0].1, "unknown: no response");
+    }
+}
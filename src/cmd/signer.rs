@@ -0,0 +1,589 @@
+//! # Transaction signing
+//! Refer [EIP-155](https://github.com/ethereum/EIPs/blob/master/EIPS/eip-155.md),
+//! [EIP-2718](https://github.com/ethereum/EIPs/blob/master/EIPS/eip-2718.md),
+//! [EIP-2930](https://github.com/ethereum/EIPs/blob/master/EIPS/eip-2930.md) and
+//! [EIP-1559](https://github.com/ethereum/EIPs/blob/master/EIPS/eip-1559.md) for more info
+
+use super::rlp::{
+    decode_list, encode_bytes, encode_list, encode_u64, encode_uint, to_u64, u64_bytes,
+};
+use super::{Address, Error, PrivateKey, Transaction};
+use emerald::{keccak256, trim_hex};
+use hex::{FromHex, ToHex};
+use std::str::FromStr;
+
+/// Type of EIP-2930 access list transaction
+pub const ACCESS_LIST_TX_TYPE: u8 = 0x01;
+
+/// Type of EIP-1559 dynamic-fee transaction
+pub const DYNAMIC_FEE_TX_TYPE: u8 = 0x02;
+
+/// Max chain id for which EIP-155 `v = chain_id * 2 + 35 + recovery_id` fits into `u64`
+pub const MAX_CHAIN_ID: u64 = (::std::u64::MAX - 36) / 2;
+
+/// Intrinsic gas per address in access list
+const ACCESS_LIST_ADDRESS_GAS: u64 = 2400;
+
+/// Intrinsic gas per storage key in access list
+const ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1900;
+
+/// Entry of access list, in format of JSON RPC
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccessItem {
+    pub address: String,
+    #[serde(rename = "storageKeys")]
+    pub storage_keys: Vec<String>,
+}
+
+/// Transaction with fields of EIP-2718 typed transactions
+pub struct TypedTransaction {
+    /// Common fields, `gas_price` is max fee per gas for dynamic-fee transaction
+    pub tr: Transaction,
+    /// Max priority fee per gas, set for dynamic-fee transaction only
+    pub max_priority_fee: Option<[u8; 32]>,
+    /// Addresses and storage keys accessed by transaction
+    pub access_list: Vec<AccessItem>,
+}
+
+impl TypedTransaction {
+    /// Legacy transaction
+    pub fn legacy(tr: Transaction) -> TypedTransaction {
+        TypedTransaction {
+            tr,
+            max_priority_fee: None,
+            access_list: vec![],
+        }
+    }
+
+    /// Whether transaction is EIP-1559 dynamic-fee transaction
+    pub fn is_dynamic_fee(&self) -> bool {
+        self.max_priority_fee.is_some()
+    }
+
+    /// EIP-2718 transaction type, `None` for legacy transaction
+    pub fn tx_type(&self) -> Option<u8> {
+        if self.is_dynamic_fee() {
+            Some(DYNAMIC_FEE_TX_TYPE)
+        } else if !self.access_list.is_empty() {
+            Some(ACCESS_LIST_TX_TYPE)
+        } else {
+            None
+        }
+    }
+}
+
+/// Intrinsic gas paid for access list
+///
+/// # Arguments:
+///
+/// * list - access list
+///
+pub fn access_list_gas(list: &[AccessItem]) -> u64 {
+    list.iter()
+        .map(|i| {
+            ACCESS_LIST_ADDRESS_GAS + ACCESS_LIST_STORAGE_KEY_GAS * i.storage_keys.len() as u64
+        })
+        .sum()
+}
+
+/// Raw bytes of address
+pub fn address_bytes(addr: &Address) -> Result<Vec<u8>, Error> {
+    Vec::from_hex(&addr.to_string()[2..]).map_err(Error::from)
+}
+
+/// RLP items of transaction body, common for signing hash and signed transaction
+///
+/// # Arguments:
+///
+/// * tr - transaction
+///
+fn tx_items(tr: &Transaction) -> Result<Vec<Vec<u8>>, Error> {
+    let to = match tr.to {
+        Some(ref a) => address_bytes(a)?,
+        None => vec![],
+    };
+
+    Ok(vec![
+        encode_u64(tr.nonce),
+        encode_uint(&tr.gas_price),
+        encode_u64(tr.gas_limit),
+        encode_bytes(&to),
+        encode_uint(&tr.value),
+        encode_bytes(&tr.data),
+    ])
+}
+
+/// Hash to be signed, with replay protection for chain id
+///
+/// # Arguments:
+///
+/// * tr - transaction
+/// * chain_id - chain id
+///
+pub fn signing_hash(tr: &Transaction, chain_id: u64) -> Result<[u8; 32], Error> {
+    let mut items = tx_items(tr)?;
+    items.push(encode_u64(chain_id));
+    items.push(encode_u64(0));
+    items.push(encode_u64(0));
+
+    Ok(keccak256(&encode_list(&items)))
+}
+
+/// Sign transaction and encode it into raw RLP,
+/// supports chain ids up to `MAX_CHAIN_ID`
+///
+/// # Arguments:
+///
+/// * tr - transaction
+/// * pk - private key
+/// * chain_id - chain id
+///
+pub fn sign_legacy(tr: &Transaction, pk: &PrivateKey, chain_id: u64) -> Result<Vec<u8>, Error> {
+    let sig = pk.sign_hash(signing_hash(tr, chain_id)?)?;
+    let v = chain_id
+        .checked_mul(2)
+        .and_then(|v| v.checked_add(u64::from(sig.v - 27) + 35))
+        .ok_or_else(|| {
+            Error::ExecError(format!(
+                "Chain id {} is too big for transaction signature",
+                chain_id
+            ))
+        })?;
+
+    let mut items = tx_items(tr)?;
+    items.push(encode_bytes(&u64_bytes(v)));
+    items.push(encode_uint(&sig.r));
+    items.push(encode_uint(&sig.s));
+
+    Ok(encode_list(&items))
+}
+
+/// RLP-encoded access list
+///
+/// # Arguments:
+///
+/// * list - access list
+///
+fn encode_access_list(list: &[AccessItem]) -> Result<Vec<u8>, Error> {
+    let mut items = vec![];
+    for item in list {
+        let addr = address_bytes(&Address::from_str(&item.address)?)?;
+        let mut keys = vec![];
+        for k in &item.storage_keys {
+            let key = Vec::from_hex(trim_hex(k))?;
+            if key.len() != 32 {
+                return Err(Error::ExecError(format!("Invalid storage key: {}", k)));
+            }
+            keys.push(encode_bytes(&key));
+        }
+        items.push(encode_list(&[encode_bytes(&addr), encode_list(&keys)]));
+    }
+
+    Ok(encode_list(&items))
+}
+
+/// RLP items of typed transaction body,
+/// common for signing hash and signed transaction
+///
+/// # Arguments:
+///
+/// * tx - transaction
+/// * tx_type - EIP-2718 transaction type
+/// * chain_id - chain id
+///
+fn typed_items(tx: &TypedTransaction, tx_type: u8, chain_id: u64) -> Result<Vec<Vec<u8>>, Error> {
+    let tr = &tx.tr;
+    let to = match tr.to {
+        Some(ref a) => address_bytes(a)?,
+        None => vec![],
+    };
+
+    let mut items = vec![encode_u64(chain_id), encode_u64(tr.nonce)];
+    if tx_type == DYNAMIC_FEE_TX_TYPE {
+        items.push(encode_uint(&tx.max_priority_fee.unwrap_or_default()));
+    }
+    items.push(encode_uint(&tr.gas_price));
+    items.push(encode_u64(tr.gas_limit));
+    items.push(encode_bytes(&to));
+    items.push(encode_uint(&tr.value));
+    items.push(encode_bytes(&tr.data));
+    items.push(encode_access_list(&tx.access_list)?);
+
+    Ok(items)
+}
+
+/// Typed transaction envelope, type byte followed by RLP payload
+fn envelope(tx_type: u8, items: &[Vec<u8>]) -> Vec<u8> {
+    let mut res = vec![tx_type];
+    res.extend_from_slice(&encode_list(items));
+    res
+}
+
+/// Hash of typed transaction to be signed
+///
+/// # Arguments:
+///
+/// * tx - transaction
+/// * tx_type - EIP-2718 transaction type
+/// * chain_id - chain id
+///
+pub fn typed_signing_hash(
+    tx: &TypedTransaction,
+    tx_type: u8,
+    chain_id: u64,
+) -> Result<[u8; 32], Error> {
+    let items = typed_items(tx, tx_type, chain_id)?;
+    Ok(keccak256(&envelope(tx_type, &items)))
+}
+
+/// Sign typed transaction and encode it into EIP-2718 envelope
+///
+/// # Arguments:
+///
+/// * tx - transaction
+/// * tx_type - EIP-2718 transaction type
+/// * pk - private key
+/// * chain_id - chain id
+///
+pub fn sign_typed(
+    tx: &TypedTransaction,
+    tx_type: u8,
+    pk: &PrivateKey,
+    chain_id: u64,
+) -> Result<Vec<u8>, Error> {
+    let sig = pk.sign_hash(typed_signing_hash(tx, tx_type, chain_id)?)?;
+
+    let mut items = typed_items(tx, tx_type, chain_id)?;
+    items.push(encode_u64(u64::from(sig.v - 27)));
+    items.push(encode_uint(&sig.r));
+    items.push(encode_uint(&sig.s));
+
+    Ok(envelope(tx_type, &items))
+}
+
+/// Sign transaction of any supported type. Legacy transactions for chain ids
+/// supported by `emerald-rs` are signed by it, bigger chain ids by `sign_legacy`
+///
+/// # Arguments:
+///
+/// * tx - transaction
+/// * pk - private key
+/// * chain_id - chain id
+///
+pub fn sign(tx: &TypedTransaction, pk: &PrivateKey, chain_id: u64) -> Result<Vec<u8>, Error> {
+    match tx.tx_type() {
+        Some(t) => sign_typed(tx, t, pk, chain_id),
+        None if chain_id <= u64::from(::std::u8::MAX) => tx
+            .tr
+            .to_signed_raw(*pk, chain_id as u8)
+            .map_err(Error::from),
+        None => sign_legacy(&tx.tr, pk, chain_id),
+    }
+}
+
+/// Extract chain id from signed raw transaction.
+/// Returns `None` for transaction without replay protection
+///
+/// # Arguments:
+///
+/// * raw - signed RLP-encoded transaction
+///
+pub fn raw_chain_id(raw: &[u8]) -> Result<Option<u64>, Error> {
+    match raw.first() {
+        // typed transaction envelope, chain id is the first field
+        Some(&t) if t <= 0x7f => {
+            let items = decode_list(&raw[1..])?;
+            match items.first() {
+                Some(id) => to_u64(id).map(Some),
+                None => Err(Error::ExecError("Invalid typed transaction".to_string())),
+            }
+        }
+        Some(_) => {
+            let items = decode_list(raw)?;
+            if items.len() != 9 {
+                return Err(Error::ExecError("Invalid signed transaction".to_string()));
+            }
+            let v = to_u64(items[6])?;
+            if v >= 35 {
+                Ok(Some((v - 35) / 2))
+            } else {
+                Ok(None)
+            }
+        }
+        None => Err(Error::ExecError("Empty transaction".to_string())),
+    }
+}
+
+/// Fields of signed transaction, available without signature recovery
+#[derive(Debug, PartialEq)]
+pub struct RawTxInfo {
+    pub nonce: u64,
+    pub to: Option<Address>,
+    pub value: [u8; 32],
+}
+
+/// Decode nonce, receiver and value from signed raw transaction
+///
+/// # Arguments:
+///
+/// * raw - signed RLP-encoded transaction
+///
+pub fn raw_tx_info(raw: &[u8]) -> Result<RawTxInfo, Error> {
+    // positions of nonce, receiver and value
+    let (items, pos) = match raw.first() {
+        Some(&0x01) => (decode_list(&raw[1..])?, (1, 4, 5)),
+        Some(&0x02) => (decode_list(&raw[1..])?, (1, 5, 6)),
+        Some(&t) if t <= 0x7f => {
+            return Err(Error::ExecError(format!(
+                "Unsupported transaction type {}",
+                t
+            )))
+        }
+        Some(_) => (decode_list(raw)?, (0, 3, 4)),
+        None => return Err(Error::ExecError("Empty transaction".to_string())),
+    };
+    if items.len() <= pos.2 || items[pos.2].len() > 32 {
+        return Err(Error::ExecError("Invalid signed transaction".to_string()));
+    }
+
+    let to = match items[pos.1] {
+        b if b.is_empty() => None,
+        b => Some(Address::from_str(&format!("0x{}", b.to_hex()))?),
+    };
+    let mut value = [0u8; 32];
+    let v = items[pos.2];
+    value[32 - v.len()..].copy_from_slice(v);
+
+    Ok(RawTxInfo {
+        nonce: to_u64(items[pos.0])?,
+        to,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example transaction from EIP-155
+    fn eip155_tx() -> Transaction {
+        let mut value = [0u8; 32];
+        value[24..].copy_from_slice(&[0x0d, 0xe0, 0xb6, 0xb3, 0xa7, 0x64, 0x00, 0x00]);
+        let mut gas_price = [0u8; 32];
+        gas_price[27..].copy_from_slice(&[0x04, 0xa8, 0x17, 0xc8, 0x00]);
+
+        Transaction {
+            nonce: 9,
+            gas_price,
+            gas_limit: 21000,
+            to: Some(Address::from_str("0x3535353535353535353535353535353535353535").unwrap()),
+            value,
+            data: vec![],
+        }
+    }
+
+    #[test]
+    fn should_calculate_signing_hash() {
+        assert_eq!(
+            signing_hash(&eip155_tx(), 1).unwrap().to_hex(),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+    }
+
+    #[test]
+    fn should_sign_eip155_tx() {
+        let pk = PrivateKey::from_str(
+            "0x4646464646464646464646464646464646464646464646464646464646464646",
+        ).unwrap();
+
+        assert_eq!(
+            sign_legacy(&eip155_tx(), &pk, 1).unwrap().to_hex(),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764\
+             00008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9\
+             d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+    }
+
+    #[test]
+    fn should_sign_legacy_same_as_emerald() {
+        let pk = PrivateKey::from_str(
+            "0x4646464646464646464646464646464646464646464646464646464646464646",
+        ).unwrap();
+        let mut contract = eip155_tx();
+        contract.to = None;
+        contract.data = vec![0x60, 0x80, 0x60, 0x40, 0x52];
+        let mut zero = eip155_tx();
+        zero.nonce = 0;
+        zero.value = [0u8; 32];
+
+        for tr in &[eip155_tx(), contract, zero] {
+            for &id in &[1u8, 2, 3, 28, 61, 62, 63, 255] {
+                assert_eq!(
+                    sign_legacy(tr, &pk, u64::from(id)).unwrap(),
+                    tr.to_signed_raw(pk, id).unwrap(),
+                    "chain id {}",
+                    id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn should_reject_too_big_chain_id() {
+        let pk = PrivateKey::from_str(
+            "0x4646464646464646464646464646464646464646464646464646464646464646",
+        ).unwrap();
+
+        assert!(sign_legacy(&eip155_tx(), &pk, MAX_CHAIN_ID).is_ok());
+        assert!(sign_legacy(&eip155_tx(), &pk, ::std::u64::MAX).is_err());
+    }
+
+    #[test]
+    fn should_extract_chain_id() {
+        let raw = Vec::from_hex(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764\
+             00008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9\
+             d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+        ).unwrap();
+        assert_eq!(raw_chain_id(&raw).unwrap(), Some(1));
+
+        let pk = PrivateKey::from_str(
+            "0x4646464646464646464646464646464646464646464646464646464646464646",
+        ).unwrap();
+        let raw = sign_legacy(&eip155_tx(), &pk, 1337).unwrap();
+        assert_eq!(raw_chain_id(&raw).unwrap(), Some(1337));
+
+        assert!(raw_chain_id(&[]).is_err());
+        assert!(raw_chain_id(&[0xc0]).is_err());
+    }
+
+    #[test]
+    fn should_decode_raw_tx_info() {
+        let pk = PrivateKey::from_str(
+            "0x4646464646464646464646464646464646464646464646464646464646464646",
+        ).unwrap();
+        let tr = eip155_tx();
+        let raw = sign_legacy(&tr, &pk, 1).unwrap();
+
+        assert_eq!(
+            raw_tx_info(&raw).unwrap(),
+            RawTxInfo {
+                nonce: tr.nonce,
+                to: tr.to,
+                value: tr.value,
+            }
+        );
+        assert!(raw_tx_info(&[]).is_err());
+        assert!(raw_tx_info(&[0xc0]).is_err());
+    }
+
+    fn access_list() -> Vec<AccessItem> {
+        vec![AccessItem {
+            address: "0x3535353535353535353535353535353535353535".to_string(),
+            storage_keys: vec![
+                format!("0x{}", "00".repeat(32)),
+                format!("0x{}01", "00".repeat(31)),
+            ],
+        }]
+    }
+
+    /// EIP-1559 transaction with the same body as EIP-155 example
+    fn dynamic_fee_tx(access_list: Vec<AccessItem>) -> TypedTransaction {
+        let mut tr = eip155_tx();
+        tr.gas_price = [0u8; 32];
+        tr.gas_price[27..].copy_from_slice(&[0x0b, 0xa4, 0x3b, 0x74, 0x00]);
+        let mut tip = [0u8; 32];
+        tip[28..].copy_from_slice(&[0x77, 0x35, 0x94, 0x00]);
+
+        TypedTransaction {
+            tr,
+            max_priority_fee: Some(tip),
+            access_list,
+        }
+    }
+
+    #[test]
+    fn should_calculate_dynamic_fee_signing_hash() {
+        assert_eq!(
+            typed_signing_hash(&dynamic_fee_tx(vec![]), DYNAMIC_FEE_TX_TYPE, 1)
+                .unwrap()
+                .to_hex(),
+            "71773ae6d712557e993f022d95bcbe17862f4e1c7b8c8bb3929ee5955411fad2"
+        );
+
+        assert_eq!(
+            typed_signing_hash(&dynamic_fee_tx(access_list()), DYNAMIC_FEE_TX_TYPE, 1)
+                .unwrap()
+                .to_hex(),
+            "278c615c59b081ec49c84daa3ebdfb977ac69e1eb9394f8ffbf976d38e28a235"
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_storage_key() {
+        let access_list = vec![AccessItem {
+            address: "0x3535353535353535353535353535353535353535".to_string(),
+            storage_keys: vec!["0x01".to_string()],
+        }];
+        let tx = dynamic_fee_tx(access_list);
+        assert!(typed_signing_hash(&tx, DYNAMIC_FEE_TX_TYPE, 1).is_err());
+    }
+
+    #[test]
+    fn should_sign_dynamic_fee_tx() {
+        let pk = PrivateKey::from_str(
+            "0x4646464646464646464646464646464646464646464646464646464646464646",
+        ).unwrap();
+        let tx = dynamic_fee_tx(vec![]);
+        let raw = sign(&tx, &pk, 1337).unwrap();
+
+        assert_eq!(raw[0], DYNAMIC_FEE_TX_TYPE);
+        assert_eq!(raw_chain_id(&raw).unwrap(), Some(1337));
+        assert_eq!(
+            raw_tx_info(&raw).unwrap(),
+            RawTxInfo {
+                nonce: tx.tr.nonce,
+                to: tx.tr.to,
+                value: tx.tr.value,
+            }
+        );
+
+        let legacy = sign(&TypedTransaction::legacy(eip155_tx()), &pk, 1).unwrap();
+        assert_eq!(legacy, sign_legacy(&eip155_tx(), &pk, 1).unwrap());
+    }
+
+    #[test]
+    fn should_sign_access_list_tx() {
+        let pk = PrivateKey::from_str(
+            "0x4646464646464646464646464646464646464646464646464646464646464646",
+        ).unwrap();
+        let tx = TypedTransaction {
+            access_list: access_list(),
+            ..TypedTransaction::legacy(eip155_tx())
+        };
+        assert_eq!(tx.tx_type(), Some(ACCESS_LIST_TX_TYPE));
+        assert_eq!(
+            typed_signing_hash(&tx, ACCESS_LIST_TX_TYPE, 1)
+                .unwrap()
+                .to_hex(),
+            "49dee65a8fb40f8ac6f622941f5b08cad0aa07d9a39c7a898f3e05dd33f51958"
+        );
+
+        let raw = sign(&tx, &pk, 61).unwrap();
+        assert_eq!(raw[0], ACCESS_LIST_TX_TYPE);
+        assert_eq!(raw_chain_id(&raw).unwrap(), Some(61));
+        assert_eq!(raw_tx_info(&raw).unwrap().nonce, 9);
+        assert_eq!(access_list_gas(&tx.access_list), 2400 + 2 * 1900);
+    }
+
+    #[test]
+    fn should_sign_for_large_chain_id() {
+        let pk = PrivateKey::from_str(
+            "0x4646464646464646464646464646464646464646464646464646464646464646",
+        ).unwrap();
+
+        let raw = sign_legacy(&eip155_tx(), &pk, 1337).unwrap();
+        // v = recovery id + 1337 * 2 + 35 is encoded as two bytes
+        assert!(raw.windows(3).any(|w| w == [0x82, 0x0a, 0x95] || w == [0x82, 0x0a, 0x96]));
+    }
+}
@@ -0,0 +1,207 @@
+//! # Keystores, address books, contracts and local transaction data
+//! for built-in and custom chains
+
+use super::chain::ChainDef;
+use super::history::TxHistory;
+use super::nonce::NonceTracker;
+use super::{Error, KeyfileStorage, StorageController};
+use emerald::storage::{AddressbookStorage, ContractStorage, FsStorage};
+use fs2::FileExt;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Keystore folder inside chain directory
+const KEYSTORE_DIR: &str = "keystore";
+
+/// Address book folder inside chain directory
+const ADDRESSBOOK_DIR: &str = "addressbook";
+
+/// Contracts folder inside chain directory
+const CONTRACTS_DIR: &str = "contracts";
+
+/// Storages of custom chain, folders are created on first use
+struct CustomStorage {
+    keystore: Box<KeyfileStorage>,
+    addressbook: AddressbookStorage,
+    contracts: ContractStorage,
+}
+
+impl CustomStorage {
+    /// Create storages inside chain directory
+    fn new(dir: &Path) -> CustomStorage {
+        CustomStorage {
+            keystore: Box::new(FsStorage::new(dir.join(KEYSTORE_DIR))),
+            addressbook: AddressbookStorage::new(dir.join(ADDRESSBOOK_DIR)),
+            contracts: ContractStorage::new(dir.join(CONTRACTS_DIR)),
+        }
+    }
+}
+
+/// Storages for all known chains. Built-in chains are served
+/// by `StorageController`, custom chains get own keystore, address book
+/// and contracts at `<base-path>/<chain>/{keystore,addressbook,contracts}`
+pub struct Storages {
+    pub ctrl: StorageController,
+    custom: HashMap<String, CustomStorage>,
+    base_path: PathBuf,
+}
+
+impl Storages {
+    /// Create storages
+    ///
+    /// # Arguments:
+    ///
+    /// * base_path - storage base path
+    /// * chains - user-defined chains
+    ///
+    pub fn new(base_path: &Path, chains: &[ChainDef]) -> Result<Storages, Error> {
+        let ctrl = StorageController::new(base_path.to_path_buf())?;
+
+        let custom = chains
+            .iter()
+            .filter(|c| c.is_custom())
+            .map(|c| (c.name.clone(), CustomStorage::new(&base_path.join(&c.name))))
+            .collect();
+
+        Ok(Storages {
+            ctrl,
+            custom,
+            base_path: base_path.to_path_buf(),
+        })
+    }
+
+    /// Keystore for selected chain
+    ///
+    /// # Arguments:
+    ///
+    /// * chain - chain name
+    ///
+    pub fn get_keystore(&self, chain: &str) -> Result<&Box<KeyfileStorage>, Error> {
+        match self.custom.get(chain) {
+            Some(s) => {
+                self.create_dir(chain, KEYSTORE_DIR)?;
+                Ok(&s.keystore)
+            }
+            None => self.ctrl.get_keystore(chain).map_err(Error::from),
+        }
+    }
+
+    /// Address book for selected chain
+    ///
+    /// # Arguments:
+    ///
+    /// * chain - chain name
+    ///
+    pub fn get_addressbook(&self, chain: &str) -> Result<&AddressbookStorage, Error> {
+        match self.custom.get(chain) {
+            Some(s) => {
+                self.create_dir(chain, ADDRESSBOOK_DIR)?;
+                Ok(&s.addressbook)
+            }
+            None => {
+                let book: &AddressbookStorage = self.ctrl.get_addressbook(chain)?;
+                Ok(book)
+            }
+        }
+    }
+
+    /// Contracts for selected chain
+    ///
+    /// # Arguments:
+    ///
+    /// * chain - chain name
+    ///
+    pub fn get_contracts(&self, chain: &str) -> Result<&ContractStorage, Error> {
+        match self.custom.get(chain) {
+            Some(s) => {
+                self.create_dir(chain, CONTRACTS_DIR)?;
+                Ok(&s.contracts)
+            }
+            None => {
+                let contracts: &ContractStorage = self.ctrl.get_contracts(chain)?;
+                Ok(contracts)
+            }
+        }
+    }
+
+    /// Folder with keyfiles of selected chain, the same for built-in
+    /// and custom chains
+    ///
+    /// # Arguments:
+    ///
+    /// * chain - chain name
+    ///
+    pub fn keyfile_dir(&self, chain: &str) -> PathBuf {
+        self.base_path.join(chain).join(KEYSTORE_DIR)
+    }
+
+    /// Create storage folder of custom chain, if missing
+    fn create_dir(&self, chain: &str, name: &str) -> Result<(), Error> {
+        fs::create_dir_all(self.base_path.join(chain).join(name)).map_err(Error::from)
+    }
+
+    /// Tracker of used nonces for selected chain
+    ///
+    /// # Arguments:
+    ///
+    /// * chain - chain name
+    ///
+    pub fn get_nonces(&self, chain: &str) -> NonceTracker {
+        NonceTracker::new(&self.base_path, chain)
+    }
+
+    /// Journal of signed and sent transactions for selected chain
+    ///
+    /// # Arguments:
+    ///
+    /// * chain - chain name
+    ///
+    pub fn get_history(&self, chain: &str) -> TxHistory {
+        TxHistory::new(&self.base_path, chain)
+    }
+}
+
+/// Take exclusive advisory lock for file, waits for lock held by
+/// another process. Lock is kept in `<path>.lock`, because the file
+/// itself is replaced on write, and is released when returned file
+/// is dropped or the process exits
+///
+/// # Arguments:
+///
+/// * path - locked file
+///
+pub fn lock_file(path: &Path) -> Result<File, Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let lock = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(path.with_extension("lock"))?;
+    lock.lock_exclusive()?;
+    Ok(lock)
+}
+
+/// Write file through temporary file and rename,
+/// so readers never see partially written content
+///
+/// # Arguments:
+///
+/// * path - target file
+/// * data - file content
+///
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("tmp.{}", process::id()));
+    File::create(&tmp).and_then(|mut f| {
+        f.write_all(data)?;
+        f.sync_all()
+    })?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
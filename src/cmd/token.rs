@@ -0,0 +1,338 @@
+//! # ERC-20 token related subcommands
+
+use super::abi::{decode_string, decode_u64, decode_uint, encode_address, encode_call};
+use super::address::resolve_address;
+use super::arg_handlers::*;
+use super::chain::Chain;
+use super::storage::{write_atomic, Storages};
+use super::transaction::{confirm_and_sign, fill_tx, remember};
+use super::units::{format_units, parse_units};
+use super::{rpc, Address, EnvVars, Error, ExecResult, Transaction};
+use audit::{AuditLog, Event};
+use clap::ArgMatches;
+use hex::ToHex;
+use serde_json;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Token list filename inside chain directory
+const TOKENS_FILE: &str = "tokens.json";
+
+/// Registered ERC-20 token
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Token {
+    pub alias: String,
+    pub address: String,
+    pub symbol: String,
+    pub decimals: usize,
+}
+
+/// Local list of token aliases
+pub struct TokenList {
+    path: PathBuf,
+}
+
+impl TokenList {
+    /// Create token list for selected chain
+    ///
+    /// # Arguments:
+    ///
+    /// * base_path - storage base path
+    /// * chain - chain name
+    ///
+    pub fn new<P: AsRef<Path>>(base_path: P, chain: &str) -> TokenList {
+        TokenList {
+            path: base_path.as_ref().join(chain).join(TOKENS_FILE),
+        }
+    }
+
+    /// Read all registered tokens
+    pub fn list(&self) -> Result<Vec<Token>, Error> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut json = String::new();
+        File::open(&self.path).and_then(|mut f| f.read_to_string(&mut json))?;
+        serde_json::from_str(&json).map_err(Error::from)
+    }
+
+    /// Search token by alias
+    pub fn get(&self, alias: &str) -> Result<Option<Token>, Error> {
+        Ok(self.list()?.into_iter().find(|t| t.alias == alias))
+    }
+
+    /// Add new token, or replace existing one with the same alias
+    pub fn put(&self, token: Token) -> Result<(), Error> {
+        let mut tokens = self.list()?;
+        tokens.retain(|t| t.alias != token.alias);
+        tokens.push(token);
+        self.save(&tokens)
+    }
+
+    /// Remove token by alias
+    pub fn remove(&self, alias: &str) -> Result<(), Error> {
+        let mut tokens = self.list()?;
+        let len = tokens.len();
+        tokens.retain(|t| t.alias != alias);
+        if tokens.len() == len {
+            return Err(Error::ExecError(format!("Unknown token: {}", alias)));
+        }
+        self.save(&tokens)
+    }
+
+    fn save(&self, tokens: &[Token]) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(tokens)?;
+        write_atomic(&self.path, json.as_bytes())
+    }
+}
+
+/// Token commands
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - keystores and address book
+/// * env - environment variables
+/// * audit - audit log
+/// * tokens - local token list
+/// * chain - selected chain
+///
+pub fn token_cmd(
+    matches: &ArgMatches,
+    storages: &Storages,
+    env: &EnvVars,
+    audit: &AuditLog,
+    tokens: &TokenList,
+    chain: &Chain,
+) -> ExecResult {
+    match matches.subcommand() {
+        ("add", Some(sub_m)) => add(sub_m, env, tokens),
+        ("list", Some(_)) => list(tokens),
+        ("remove", Some(sub_m)) => tokens.remove(sub_m.value_of("alias").expect("Required alias")),
+        ("balance", Some(sub_m)) => balance(sub_m, storages, env, tokens, &chain.name),
+        ("transfer", Some(sub_m)) => transfer(sub_m, storages, env, audit, tokens, chain),
+        _ => Err(Error::ExecError(
+            "Invalid token subcommand. Use `emerald token -h` for help".to_string(),
+        )),
+    }
+}
+
+/// Register token alias. Missing `symbol` and `decimals`
+/// are requested from the token contract
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * env - environment variables
+/// * tokens - local token list
+///
+fn add(matches: &ArgMatches, env: &EnvVars, tokens: &TokenList) -> ExecResult {
+    let alias = matches.value_of("alias").expect("Required alias");
+    let address = get_address(matches, "address")?;
+
+    let symbol = match matches.value_of("symbol") {
+        Some(s) => s.to_string(),
+        None => get_upstream(matches, env).and_then(|rpc| request_symbol(&rpc, &address))?,
+    };
+    let decimals = match matches.value_of("decimals") {
+        Some(d) => d.parse::<usize>()?,
+        None => get_upstream(matches, env).and_then(|rpc| request_decimals(&rpc, &address))?,
+    };
+
+    tokens.put(Token {
+        alias: alias.to_string(),
+        address: address.to_string(),
+        symbol,
+        decimals,
+    })
+}
+
+/// List registered tokens
+///
+/// # Arguments:
+///
+/// * tokens - local token list
+///
+fn list(tokens: &TokenList) -> ExecResult {
+    println!(
+        "{0: <20} {1: <45} {2: <10} {3}",
+        "ALIAS", "ADDRESS", "SYMBOL", "DECIMALS"
+    );
+    for t in tokens.list()? {
+        println!(
+            "{0: <20} {1: <45} {2: <10} {3}",
+            t.alias, t.address, t.symbol, t.decimals
+        );
+    }
+
+    Ok(())
+}
+
+/// Show token balance for address
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - keystores and address book
+/// * env - environment variables
+/// * tokens - local token list
+/// * chain - chain name
+///
+fn balance(
+    matches: &ArgMatches,
+    storages: &Storages,
+    env: &EnvVars,
+    tokens: &TokenList,
+    chain: &str,
+) -> ExecResult {
+    let rpc = get_upstream(matches, env)?;
+    let token = resolve_token(matches, &rpc, tokens)?;
+    let holder = resolve_address(
+        matches.value_of("address").expect("Required address"),
+        storages,
+        chain,
+    )?;
+
+    let data = encode_call("balanceOf(address)", &[encode_address(&holder)]);
+    let balance = rpc::call(&rpc, &token_address(&token)?, &data).and_then(|d| decode_uint(&d))?;
+
+    info!("Balance of {} for {} account", &token.symbol, &holder);
+    println!("{} {}", format_units(&balance, token.decimals), token.symbol);
+
+    Ok(())
+}
+
+/// Create and sign `transfer(address,uint256)` transaction
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * storages - keystores and address book
+/// * env - environment variables
+/// * audit - audit log
+/// * tokens - local token list
+/// * chain - selected chain
+///
+fn transfer(
+    matches: &ArgMatches,
+    storages: &Storages,
+    env: &EnvVars,
+    audit: &AuditLog,
+    tokens: &TokenList,
+    chain: &Chain,
+) -> ExecResult {
+    let rpc = get_upstream(matches, env)?;
+    let token = resolve_token(matches, &rpc, tokens)?;
+    let from = get_address(matches, "from")?;
+    let to = resolve_address(
+        matches.value_of("to").expect("Required receiver"),
+        storages,
+        &chain.name,
+    )?;
+    let amount_str = matches.value_of("amount").expect("Required amount");
+    let amount = parse_units(amount_str, token.decimals)?;
+
+    let data = encode_call("transfer(address,uint256)", &[encode_address(&to), amount]);
+    let tr = Transaction {
+        nonce: 0,
+        gas_price: [0u8; 32],
+        gas_limit: 0,
+        to: Some(token_address(&token)?),
+        value: [0u8; 32],
+        data,
+    };
+    let nonces = storages.get_nonces(&chain.name);
+    let (tx, reserved) = fill_tx(matches, env, &nonces, chain, &from, tr)?;
+
+    eprintln!("Transfer {} {} to {}", amount_str, &token.symbol, &to);
+    let event = Event {
+        to: Some(to.to_string()),
+        value: Some(format!("{} {}", amount_str, &token.symbol)),
+        ..Event::new("token transfer", &chain.name)
+    };
+    let signed = confirm_and_sign(matches, storages, audit, chain, event, &from, &tx)?;
+    remember(storages, chain, &from, &tx, &signed, reserved)?;
+
+    println!("{}", signed.to_hex());
+
+    Ok(())
+}
+
+/// Find token by alias from local token list, or by contract address
+///
+/// # Arguments:
+///
+/// * matches - arguments supplied from command-line
+/// * rpc - connector to remote node
+/// * tokens - local token list
+///
+fn resolve_token(
+    matches: &ArgMatches,
+    rpc: &rpc::RpcConnector,
+    tokens: &TokenList,
+) -> Result<Token, Error> {
+    let s = matches.value_of("token").expect("Required token");
+    if let Some(t) = tokens.get(s)? {
+        return Ok(t);
+    }
+
+    let address = Address::from_str(s)
+        .map_err(|_| Error::ExecError(format!("Unknown token: {}", s)))?;
+    Ok(Token {
+        alias: s.to_string(),
+        address: address.to_string(),
+        symbol: request_symbol(rpc, &address)?,
+        decimals: request_decimals(rpc, &address)?,
+    })
+}
+
+/// Parse token contract address
+fn token_address(token: &Token) -> Result<Address, Error> {
+    Address::from_str(&token.address).map_err(Error::from)
+}
+
+/// Request `symbol()` of token contract
+fn request_symbol(rpc: &rpc::RpcConnector, token: &Address) -> Result<String, Error> {
+    rpc::call(rpc, token, &encode_call("symbol()", &[])).and_then(|d| decode_string(&d))
+}
+
+/// Request `decimals()` of token contract
+fn request_decimals(rpc: &rpc::RpcConnector, token: &Address) -> Result<usize, Error> {
+    rpc::call(rpc, token, &encode_call("decimals()", &[]))
+        .and_then(|d| decode_u64(&d))
+        .map(|d| d as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn token(alias: &str) -> Token {
+        Token {
+            alias: alias.to_string(),
+            address: "0x085fb4f24031eaedbc2b611aa528f22343eb52db".to_string(),
+            symbol: "BEC".to_string(),
+            decimals: 18,
+        }
+    }
+
+    #[test]
+    fn should_remove_token() {
+        let dir = TempDir::new("emerald").unwrap();
+        let tokens = TokenList::new(dir.path(), "mainnet");
+        tokens.put(token("bec")).unwrap();
+        tokens.put(token("other")).unwrap();
+
+        tokens.remove("bec").unwrap();
+        assert!(tokens.get("bec").unwrap().is_none());
+        assert!(tokens.get("other").unwrap().is_some());
+        assert_eq!(tokens.list().unwrap().len(), 1);
+
+        assert!(tokens.remove("bec").is_err());
+        assert_eq!(tokens.list().unwrap().len(), 1);
+    }
+}
@@ -1,31 +1,66 @@
 //! # Transaction related subcommands
 
+use super::address::resolve_address;
 use super::arg_handlers::*;
-use super::{rpc, ArgMatches, EnvVars, Error, ExecResult, KeyfileStorage, PrivateKey, Transaction};
-use emerald::{to_chain_id, Address};
+use super::chain::Chain;
+use super::fee::{
+    check_gas_price, dynamic_fees, get_max_gas_price, needs_fee_history, suggest_gas_price,
+    FeeLevel,
+};
+use super::history::{normalize_hash, tx_hash, TxRecord, TxStatus};
+use super::nonce::{NonceTracker, Reservation};
+use super::signer::{
+    access_list_gas, raw_chain_id, raw_tx_info, sign, AccessItem, TypedTransaction,
+};
+use super::storage::Storages;
+use super::units::{format_units, mul_u64};
+use super::{rpc, ArgMatches, EnvVars, Error, ExecResult, PrivateKey, Transaction};
+use atty;
+use audit::{AuditLog, Event};
+use chrono::NaiveDateTime;
+use emerald::{keccak256, to_even_str, trim_hex, Address};
 use hex::{FromHex, ToHex};
+use serde_json;
+use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::str::FromStr;
 
+/// Well-known function selectors
+const KNOWN_SELECTORS: &[(&str, &str)] = &[
+    ("a9059cbb", "transfer(address,uint256)"),
+    ("095ea7b3", "approve(address,uint256)"),
+    ("23b872dd", "transferFrom(address,address,uint256)"),
+    ("70a08231", "balanceOf(address)"),
+    ("40c10f19", "mint(address,uint256)"),
+    ("42966c68", "burn(uint256)"),
+    ("d0e30db0", "deposit()"),
+    ("2e1a7d4d", "withdraw(uint256)"),
+];
+
 /// Hide account from being listed
 ///
 /// # Arguments:
 ///
 /// * matches - arguments supplied from command-line
-/// * storage - `Keyfile` storage
-/// * sec_level - key derivation depth
-/// * chain - chain name
+/// * storages - keystores and address book
+/// * env - environment variables
+/// * audit - audit log
+/// * chain - selected chain
 ///
 pub fn transaction_cmd(
     matches: &ArgMatches,
-    storage: &Box<KeyfileStorage>,
+    storages: &Storages,
     env: &EnvVars,
-    chain: &str,
+    audit: &AuditLog,
+    chain: &Chain,
 ) -> ExecResult {
     match matches.subcommand() {
-        ("new", Some(sub_m)) => new(sub_m, env, storage, chain),
-        ("send", Some(sub_m)) => send(sub_m),
+        ("new", Some(sub_m)) => new(sub_m, env, storages, audit, chain),
+        ("send", Some(sub_m)) => send(sub_m, env, storages, chain),
+        ("history", Some(sub_m)) => history(sub_m, env, storages, chain),
+        ("status", Some(sub_m)) => status(sub_m, env, storages, chain),
+        ("rebroadcast", Some(sub_m)) => rebroadcast(sub_m, env, storages, chain),
         _ => Err(Error::ExecError(
             "Invalid transaction subcommand. Use `emerald transaction -h` for help".to_string(),
         )),
@@ -38,32 +73,224 @@ pub fn transaction_cmd(
 ///
 ///  * matches -
 ///  * env -
-///  * storage -
-///  * chain - chain name
+///  * storages - keystores and address book
+///  * audit - audit log
+///  * chain - selected chain
 ///
 fn new(
     matches: &ArgMatches,
     env: &EnvVars,
-    storage: &Box<KeyfileStorage>,
-    chain: &str,
+    storages: &Storages,
+    audit: &AuditLog,
+    chain: &Chain,
 ) -> ExecResult {
-    let (_, kf) = get_address(matches, "address")
-        .and_then(|from| storage.search_by_address(&from).map_err(Error::from))?;
-    let pk = request_passphrase().and_then(|pass| kf.decrypt_key(&pass).map_err(Error::from))?;
-    let signed = build_tx(matches, env).and_then(|tr| sign_tx(&tr, pk, chain))?;
+    let from = get_address(matches, "from")?;
+    let nonces = storages.get_nonces(&chain.name);
+    let (tx, reserved) = build_tx(matches, env, storages, &nonces, chain)?;
+    let signed = confirm_and_sign(
+        matches,
+        storages,
+        audit,
+        chain,
+        Event::new("transaction new", &chain.name),
+        &from,
+        &tx,
+    )?;
+    remember(storages, chain, &from, &tx, &signed, reserved)?;
 
     println!("{}", signed.to_hex());
 
     Ok(())
 }
 
+/// Confirm transaction details with user, decrypt sender's key
+/// and sign transaction. Result is recorded into audit log
+///
+///  # Arguments:
+///
+///  * matches - arguments supplied from command-line
+///  * storages - keystores and address book
+///  * audit - audit log
+///  * chain - selected chain
+///  * event - audit event for the operation
+///  * from - sender address
+///  * tx - transaction to be signed
+///
+pub fn confirm_and_sign(
+    matches: &ArgMatches,
+    storages: &Storages,
+    audit: &AuditLog,
+    chain: &Chain,
+    event: Event,
+    from: &Address,
+    tx: &TypedTransaction,
+) -> Result<Vec<u8>, Error> {
+    let (_, kf) = storages
+        .get_keystore(&chain.name)?
+        .search_by_address(from)?;
+    confirm_tx(matches, tx, from, chain)?;
+
+    let mut event = event;
+    event.address = Some(from.to_string());
+    if event.to.is_none() {
+        event.to = tx.tr.to.as_ref().map(|a| a.to_string());
+    }
+    if event.value.is_none() {
+        event.value = Some(format!("0x{}", tx.tr.value.to_hex()));
+    }
+    let signed = request_passphrase()
+        .and_then(|pass| kf.decrypt_key(&pass).map_err(Error::from))
+        .and_then(|pk| sign_tx(tx, pk, chain));
+    if let Ok(ref raw) = signed {
+        event.tx_hash = Some(format!("0x{}", keccak256(raw).to_hex()));
+    }
+    audit.record(event, &signed)?;
+
+    signed
+}
+
+/// Record nonce of signed transaction and add transaction
+/// into local journal
+///
+///  # Arguments:
+///
+///  * storages - keystores and local transaction data
+///  * chain - selected chain
+///  * from - sender address
+///  * tx - signed transaction
+///  * raw - signed RLP-encoded transaction
+///  * reserved - nonce reserved for transaction, kept once it is recorded
+///
+pub fn remember(
+    storages: &Storages,
+    chain: &Chain,
+    from: &Address,
+    tx: &TypedTransaction,
+    raw: &[u8],
+    reserved: Option<Reservation>,
+) -> ExecResult {
+    storages.get_nonces(&chain.name).record(from, tx.tr.nonce)?;
+    if let Some(r) = reserved {
+        r.keep();
+    }
+
+    let record = TxRecord::new(raw, Some(from.to_string()), &chain.name, TxStatus::Signed)?;
+    storages.get_history(&chain.name).put(record)
+}
+
+/// Show transaction details and ask user to confirm signing
+///
+///  # Arguments:
+///
+///  * matches - arguments supplied from command-line
+///  * tx - transaction to be signed
+///  * from - sender address
+///  * chain - selected chain
+///
+fn confirm_tx(
+    matches: &ArgMatches,
+    tx: &TypedTransaction,
+    from: &Address,
+    chain: &Chain,
+) -> ExecResult {
+    if matches.is_present("yes") {
+        return Ok(());
+    }
+    if !atty::is(atty::Stream::Stdin) {
+        return Err(Error::ExecError(
+            "Can't confirm transaction: stdin is not a terminal. Use `--yes` to sign without \
+             confirmation"
+                .to_string(),
+        ));
+    }
+
+    let tr = &tx.tr;
+    let to = match tr.to {
+        Some(ref a) => a.to_string(),
+        None => "(contract creation)".to_string(),
+    };
+    let chain_id = chain.id()?;
+
+    eprintln!("From:      {}", from);
+    eprintln!("To:        {}", to);
+    eprintln!(
+        "Value:     {} {}",
+        format_units(&tr.value, chain.decimals),
+        chain.symbol
+    );
+    eprintln!("Gas limit: {}", tr.gas_limit);
+    match tx.max_priority_fee {
+        Some(ref tip) => {
+            eprintln!("Type:      EIP-1559");
+            eprintln!("Fee cap:   {} wei", format_units(&tr.gas_price, 0));
+            eprintln!("Tip cap:   {} wei", format_units(tip, 0));
+        }
+        None => {
+            if !tx.access_list.is_empty() {
+                eprintln!("Type:      EIP-2930");
+            }
+            eprintln!("Gas price: {} wei", format_units(&tr.gas_price, 0));
+        }
+    }
+    if !tx.access_list.is_empty() {
+        let keys: usize = tx.access_list.iter().map(|i| i.storage_keys.len()).sum();
+        eprintln!(
+            "Access:    {} addresses, {} storage keys",
+            tx.access_list.len(),
+            keys
+        );
+    }
+    eprintln!(
+        "Max fee:   {} {}",
+        format_units(&mul_u64(&tr.gas_price, tr.gas_limit)?, chain.decimals),
+        chain.symbol
+    );
+    eprintln!("Nonce:     {}", tr.nonce);
+    eprintln!("Chain:     {} ({})", chain.name, chain_id);
+    eprintln!("Data:      {} bytes", tr.data.len());
+    if let Some(f) = decode_selector(&tr.data) {
+        eprintln!("Function:  {}", f);
+    }
+
+    eprint!("Sign this transaction? [y/N]: ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    match answer.trim() {
+        "y" | "Y" | "yes" => Ok(()),
+        _ => Err(Error::ExecError("Transaction rejected by user".to_string())),
+    }
+}
+
+/// Decode function signature from transaction data
+///
+///  # Arguments:
+///
+///  * data - transaction data
+///
+fn decode_selector(data: &[u8]) -> Option<&'static str> {
+    if data.len() < 4 {
+        return None;
+    }
+
+    let selector = data[..4].to_hex();
+    KNOWN_SELECTORS
+        .iter()
+        .find(|&&(s, _)| s == selector)
+        .map(|&(_, f)| f)
+}
+
 /// Send transaction into network through provided node
 ///
 ///  # Arguments:
 ///
 ///  * matches -
+///  * env - environment variables
+///  * storages - keystores and local transaction data
+///  * chain - selected chain
 ///
-fn send(matches: &ArgMatches) -> ExecResult {
+fn send(matches: &ArgMatches, env: &EnvVars, storages: &Storages, chain: &Chain) -> ExecResult {
     let s = match matches.value_of("signed-tx") {
         Some(t) => t.to_string(),
         None => {
@@ -72,14 +299,32 @@ fn send(matches: &ArgMatches) -> ExecResult {
             tx
         }
     };
-    let tx = Vec::from_hex(s)?;
+    let tx = Vec::from_hex(trim_hex(s.trim()))?;
+    let force = matches.is_present("force");
+    let from = match matches.value_of("from") {
+        Some(_) => Some(get_address(matches, "from")?),
+        None => None,
+    };
+
+    match raw_chain_id(&tx)? {
+        Some(id) => chain.check_id(id, "Transaction", force)?,
+        None => warn!("Transaction is signed without replay protection"),
+    }
 
-    match get_upstream(matches) {
+    match get_upstream(matches, env) {
         Ok(rpc) => {
-            let tx_hash = rpc::send_transaction(&rpc, &tx)?;
+            chain.check_upstream(&rpc, force)?;
+            let hash = rpc::send_transaction(&rpc, &tx)?;
             println!("Tx hash: ");
-            println!("{}", tx_hash);
-            Ok(())
+            println!("{}", hash);
+            if let Some(url) = chain.explorer_url(&hash) {
+                println!("Explorer: {}", url);
+            }
+            if let Some(ref addr) = from {
+                let nonce = raw_tx_info(&tx)?.nonce;
+                storages.get_nonces(&chain.name).record(addr, nonce)?;
+            }
+            mark_sent(storages, chain, from.as_ref(), &tx)
         }
         Err(err) => Err(Error::ExecError(format!(
             "Can't connect to node: {}",
@@ -88,6 +333,262 @@ fn send(matches: &ArgMatches) -> ExecResult {
     }
 }
 
+/// Mark transaction from local journal as pending,
+/// transaction signed elsewhere is added to journal
+///
+///  # Arguments:
+///
+///  * storages - keystores and local transaction data
+///  * chain - selected chain
+///  * from - sender address, if known
+///  * raw - signed RLP-encoded transaction
+///
+pub fn mark_sent(
+    storages: &Storages,
+    chain: &Chain,
+    from: Option<&Address>,
+    raw: &[u8],
+) -> ExecResult {
+    let hash = tx_hash(raw);
+    storages.get_history(&chain.name).update(|records| {
+        if let Some(r) = records.iter_mut().find(|r| r.hash == hash) {
+            r.status = TxStatus::Pending;
+            r.block = None;
+            return Ok(());
+        }
+
+        let from = from.map(|a| a.to_string());
+        match TxRecord::new(raw, from, &chain.name, TxStatus::Pending) {
+            Ok(r) => records.push(r),
+            Err(e) => warn!("Can't add transaction {} to history: {}", hash, e),
+        }
+        Ok(())
+    })
+}
+
+/// Show transactions from local journal
+///
+///  # Arguments:
+///
+///  * matches - arguments supplied from command-line
+///  * env - environment variables
+///  * storages - keystores and local transaction data
+///  * chain - selected chain
+///
+fn history(matches: &ArgMatches, env: &EnvVars, storages: &Storages, chain: &Chain) -> ExecResult {
+    let mut records = storages.get_history(&chain.name).list()?;
+    if let Some(s) = matches.value_of("address") {
+        let addr = resolve_address(s, storages, &chain.name)?.to_string();
+        records.retain(|r| r.involves(&addr));
+    }
+    if matches.is_present("pending") {
+        records.retain(|r| !r.status.is_final());
+    }
+
+    if matches.is_present("json") || env.is_json_output() {
+        for r in records {
+            println!("{}", serde_json::to_string(&r)?);
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{0: <20} {1: <66} {2: >6} {3}",
+        "TIME", "HASH", "NONCE", "STATUS"
+    );
+    for r in records {
+        println!(
+            "{0: <20} {1: <66} {2: >6} {3}",
+            NaiveDateTime::from_timestamp(r.timestamp as i64, 0).format("%Y-%m-%d %H:%M:%S"),
+            r.hash,
+            r.nonce,
+            r.status,
+        );
+        if let Some(ref from) = r.from {
+            println!("{0: <20} from: {1}", "", from);
+        }
+        if let Some(ref to) = r.to {
+            println!("{0: <20} to: {1}", "", to);
+        }
+        let value = hex_to_32bytes(trim_hex(&r.value))?;
+        println!(
+            "{0: <20} value: {1} {2}",
+            "",
+            format_units(&value, chain.decimals),
+            chain.symbol
+        );
+    }
+
+    Ok(())
+}
+
+/// Refresh status of transaction through remote node.
+/// Journal is updated for known transactions
+///
+///  # Arguments:
+///
+///  * matches - arguments supplied from command-line
+///  * env - environment variables
+///  * storages - keystores and local transaction data
+///  * chain - selected chain
+///
+fn status(matches: &ArgMatches, env: &EnvVars, storages: &Storages, chain: &Chain) -> ExecResult {
+    let hash = normalize_hash(matches.value_of("hash").expect("Required transaction hash"));
+    let history = storages.get_history(&chain.name);
+    let record = history.get(&hash)?;
+
+    let rpc = get_upstream(matches, env)?;
+    let (status, block) = request_status(&rpc, &hash, record.as_ref().map(|r| r.status))?;
+    if record.is_some() {
+        history.set_status(&hash, status, block)?;
+    }
+
+    println!("Status: {}", status);
+    if let Some(b) = block {
+        println!("Block: {}", b);
+    }
+    if let Some(url) = chain.explorer_url(&hash) {
+        println!("Explorer: {}", url);
+    }
+
+    Ok(())
+}
+
+/// Resend transactions unknown to upstreams. Without transactions
+/// in arguments, pending and dropped ones from journal are used
+///
+///  # Arguments:
+///
+///  * matches - arguments supplied from command-line
+///  * env - environment variables
+///  * storages - keystores and local transaction data
+///  * chain - selected chain
+///
+fn rebroadcast(
+    matches: &ArgMatches,
+    env: &EnvVars,
+    storages: &Storages,
+    chain: &Chain,
+) -> ExecResult {
+    let force = matches.is_present("force");
+    let history = storages.get_history(&chain.name);
+
+    let raws: Vec<String> = match matches.values_of("signed-tx") {
+        Some(vals) => vals.map(|s| s.trim().to_string()).collect(),
+        None => history
+            .list()?
+            .into_iter()
+            .filter(|r| r.status == TxStatus::Pending || r.status == TxStatus::Dropped)
+            .map(|r| r.raw)
+            .collect(),
+    };
+    let mut txs = vec![];
+    for s in raws {
+        txs.push(Vec::from_hex(trim_hex(&s))?);
+    }
+    if txs.is_empty() {
+        println!("No pending transactions");
+        return Ok(());
+    }
+
+    let rpc = get_upstream(matches, env)?;
+    chain.check_upstream(&rpc, force)?;
+
+    let mut failed = 0;
+    for tx in txs {
+        let hash = tx_hash(&tx);
+        if let Some(id) = raw_chain_id(&tx)? {
+            chain.check_id(id, &format!("Transaction {}", hash), force)?;
+        }
+
+        println!("{}", hash);
+        let mut delivered = false;
+        let mut mined = false;
+        for url in &rpc.urls {
+            let single = rpc.for_url(url);
+            let outcome = match rpc::request_transaction(&single, &hash) {
+                Ok(Some(ref t)) if !t["blockNumber"].is_null() => {
+                    delivered = true;
+                    mined = true;
+                    "mined".to_string()
+                }
+                Ok(Some(_)) => {
+                    delivered = true;
+                    "known".to_string()
+                }
+                Ok(None) => match rpc::send_transaction(&single, &tx) {
+                    Ok(_) => {
+                        delivered = true;
+                        "resent".to_string()
+                    }
+                    Err(e) => format!("failed: {}", e),
+                },
+                Err(e) => format!("failed: {}", e),
+            };
+            println!("  {}: {}", rpc::hide_password(url), outcome);
+        }
+
+        if !delivered {
+            failed += 1;
+        } else if !mined {
+            mark_sent(storages, chain, None, &tx)?;
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::ExecError(format!(
+            "Can't rebroadcast {} transaction(s)",
+            failed
+        )));
+    }
+    Ok(())
+}
+
+/// Check transaction status through remote node
+///
+///  # Arguments:
+///
+///  * rpc - connector to remote nodes
+///  * hash - transaction hash
+///  * last - last known status, `None` for transaction missing in journal
+///
+///  # Return:
+///
+///  * TxStatus - current status
+///  * Option<u64> - block number for mined transaction
+///
+fn request_status(
+    rpc: &rpc::RpcConnector,
+    hash: &str,
+    last: Option<TxStatus>,
+) -> Result<(TxStatus, Option<u64>), Error> {
+    if let Some(receipt) = rpc::request_receipt(rpc, hash)? {
+        let block = match receipt["blockNumber"].as_str() {
+            Some(b) => Some(u64::from_str_radix(trim_hex(b), 16)?),
+            None => None,
+        };
+        // receipts before Byzantium have no status
+        let status = match receipt["status"].as_str().map(trim_hex) {
+            Some("0") => TxStatus::Failed,
+            _ => TxStatus::Mined,
+        };
+        return Ok((status, block));
+    }
+
+    if rpc::request_transaction(rpc, hash)?.is_some() {
+        return Ok((TxStatus::Pending, None));
+    }
+
+    match last {
+        Some(TxStatus::Signed) => Ok((TxStatus::Signed, None)),
+        Some(_) => Ok((TxStatus::Dropped, None)),
+        None => Err(Error::ExecError(format!(
+            "Transaction {} is unknown to node",
+            hash
+        ))),
+    }
+}
+
 /// Build transaction for provided arguments
 /// If argument missing, try to use envirment vars
 /// or request value through RPC
@@ -96,8 +597,17 @@ fn send(matches: &ArgMatches) -> ExecResult {
 ///
 ///  * matches -
 ///  * env -
+///  * storages - keystores and address book
+///  * nonces - local nonce tracker
+///  * chain - selected chain
 ///
-fn build_tx(matches: &ArgMatches, env: &EnvVars) -> Result<Transaction, Error> {
+fn build_tx(
+    matches: &ArgMatches,
+    env: &EnvVars,
+    storages: &Storages,
+    nonces: &NonceTracker,
+    chain: &Chain,
+) -> Result<(TypedTransaction, Option<Reservation>), Error> {
     let from = get_address(matches, "from")?;
 
     let value = matches
@@ -106,7 +616,7 @@ fn build_tx(matches: &ArgMatches, env: &EnvVars) -> Result<Transaction, Error> {
         .and_then(|s| parse_value(s))?;
 
     let to = match matches.value_of("to") {
-        Some(s) => Some(Address::from_str(s)?),
+        Some(s) => Some(resolve_address(s, storages, &chain.name)?),
         None => None,
     };
 
@@ -115,28 +625,169 @@ fn build_tx(matches: &ArgMatches, env: &EnvVars) -> Result<Transaction, Error> {
         None => vec![],
     };
 
-    Ok(Transaction {
-        nonce: get_nonce(matches, &from)?,
-        gas_price: get_gas_price(matches, env)?,
-        gas_limit: get_gas_limit(matches, env)?,
+    let tr = Transaction {
+        nonce: 0,
+        gas_price: [0u8; 32],
+        gas_limit: 0,
         to,
         value,
         data,
-    })
+    };
+    fill_tx(matches, env, nonces, chain, &from, tr)
 }
 
-/// Sign transaction with private key
+/// Fill `nonce`, fees and `gas_limit` of transaction from arguments,
+/// envirment vars or request them through RPC. Nonce from remote node
+/// is advanced past nonces used locally. Gas price can be set as level
+/// (`slow`, `standard`, `fast`) suggested from recent blocks,
+/// and is checked against max gas price.
+/// EIP-1559 dynamic-fee transaction is built if `--max-fee` or
+/// `--max-priority-fee` is set, or by default for chains with EIP-1559,
+/// unless legacy `--gas-price` is set. Otherwise transaction with
+/// access list is built as EIP-2930 transaction.
+/// Remote node is checked to serve selected chain before use
 ///
 ///  # Arguments:
 ///
-///  * matches -
-///  * env -
+///  * matches - arguments supplied from command-line
+///  * env - environment variables
+///  * nonces - local nonce tracker, nonce is reserved in it until transaction is signed
+///  * chain - selected chain
+///  * from - sender address
+///  * tr - transaction with receiver, value and data
 ///
-fn sign_tx(tr: &Transaction, pk: PrivateKey, chain: &str) -> Result<Vec<u8>, Error> {
-    if let Some(chain_id) = to_chain_id(chain) {
-        let raw = tr.to_signed_raw(pk, chain_id)?;
-        Ok(raw)
+pub fn fill_tx(
+    matches: &ArgMatches,
+    env: &EnvVars,
+    nonces: &NonceTracker,
+    chain: &Chain,
+    from: &Address,
+    tr: Transaction,
+) -> Result<(TypedTransaction, Option<Reservation>), Error> {
+    let nonce = matches.value_of("nonce");
+    let max_fee = matches.value_of("max-fee");
+    let priority_fee = matches.value_of("max-priority-fee");
+    let dynamic = max_fee.is_some()
+        || priority_fee.is_some()
+        || (chain.eip1559 && matches.value_of("gas-price").is_none());
+    let gas_price = if dynamic {
+        None
+    } else {
+        matches
+            .value_of("gas-price")
+            .or_else(|| env.emerald_gas_price.as_ref().map(String::as_str))
+    };
+    let gas = matches
+        .value_of("gas")
+        .or_else(|| env.emerald_gas.as_ref().map(String::as_str));
+    let level = gas_price.and_then(|p| FeeLevel::from_str(p).ok());
+
+    let mut tr = tr;
+    let required = rpc::TxRequired {
+        nonce: nonce.is_none(),
+        gas_price: !dynamic && gas_price.is_none(),
+        gas: gas.is_none(),
+    };
+    let rpc = if required.nonce
+        || required.gas_price
+        || required.gas
+        || level.is_some()
+        || (dynamic && needs_fee_history(max_fee, priority_fee))
+        || matches.is_present("create-access-list")
+    {
+        let rpc = get_upstream(matches, env)?;
+        chain.check_upstream(&rpc, matches.is_present("force"))?;
+        Some(rpc)
     } else {
-        Err(Error::ExecError("Invalid chain name".to_string()))
+        None
+    };
+    let remote = match rpc {
+        Some(ref rpc) => rpc::request_tx_params(rpc, from, &tr, &required)?,
+        None => rpc::TxParams::default(),
+    };
+    let access_list = get_access_list(matches, rpc.as_ref(), from, &tr)?;
+
+    let nonce = nonce.map(str::to_string).or(remote.nonce);
+    let gas = gas.map(str::to_string).or(remote.gas);
+
+    tr.nonce = u64::from_str_radix(trim_hex(&nonce.expect("Expect nonce")), 16)?;
+    tr.gas_limit = u64::from_str_radix(trim_hex(&gas.expect("Expect gas limit")), 16)?;
+    if required.gas {
+        // estimation doesn't include intrinsic gas of access list
+        tr.gas_limit += access_list_gas(&access_list);
+    }
+
+    let mut max_priority_fee = None;
+    if dynamic {
+        let (max, tip) = dynamic_fees(max_fee, priority_fee, rpc.as_ref())?;
+        tr.gas_price = max;
+        max_priority_fee = Some(tip);
+    } else {
+        tr.gas_price = match (level, rpc.as_ref()) {
+            (Some(l), Some(rpc)) => suggest_gas_price(rpc, l)?,
+            _ => {
+                let p = gas_price.map(str::to_string).or(remote.gas_price);
+                hex_to_32bytes(&to_even_str(trim_hex(&p.expect("Expect gas price"))))?
+            }
+        };
+    }
+
+    check_gas_price(&tr.gas_price, &get_max_gas_price(matches, env)?)?;
+
+    let mut reserved = None;
+    if required.nonce {
+        let r = nonces.reserve(from, tr.nonce)?;
+        tr.nonce = r.nonce();
+        reserved = Some(r);
+    }
+
+    let tx = TypedTransaction {
+        tr,
+        max_priority_fee,
+        access_list,
+    };
+    Ok((tx, reserved))
+}
+
+/// Access list from JSON file, or created by remote node
+/// with `--create-access-list`
+///
+///  # Arguments:
+///
+///  * matches - arguments supplied from command-line
+///  * rpc - connector to remote node
+///  * from - sender address
+///  * tr - transaction to be accessed
+///
+fn get_access_list(
+    matches: &ArgMatches,
+    rpc: Option<&rpc::RpcConnector>,
+    from: &Address,
+    tr: &Transaction,
+) -> Result<Vec<AccessItem>, Error> {
+    if let Some(path) = matches.value_of("access-list") {
+        let mut json = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut json))?;
+        return serde_json::from_str(&json).map_err(Error::from);
+    }
+
+    match rpc {
+        Some(rpc) if matches.is_present("create-access-list") => {
+            let list = serde_json::from_value(rpc::request_access_list(rpc, from, tr)?)?;
+            Ok(list)
+        }
+        _ => Ok(vec![]),
     }
 }
+
+/// Sign transaction with private key
+///
+///  # Arguments:
+///
+///  * tx - transaction to be signed
+///  * pk - private key
+///  * chain - selected chain
+///
+fn sign_tx(tx: &TypedTransaction, pk: PrivateKey, chain: &Chain) -> Result<Vec<u8>, Error> {
+    sign(tx, &pk, chain.id()?)
+}
@@ -0,0 +1,195 @@
+//! # Conversion of big-endian amounts to human-readable units
+
+use super::Error;
+
+/// Decimals of `ether` denominated in `wei`
+pub const ETHER_DECIMALS: usize = 18;
+
+/// Divide big-endian number in place
+///
+/// # Return:
+///
+/// * u32 - remainder of the division
+///
+fn div_rem(n: &mut [u8], d: u32) -> u32 {
+    let mut rem = 0u32;
+    for b in n.iter_mut() {
+        let cur = (rem << 8) | u32::from(*b);
+        *b = (cur / d) as u8;
+        rem = cur % d;
+    }
+    rem
+}
+
+/// Multiply big-endian number in place and add value to it
+///
+/// # Return:
+///
+/// * bool - `true` if result overflows
+///
+fn mul_add(n: &mut [u8], m: u64, a: u64) -> bool {
+    let mut carry = u128::from(a);
+    for b in n.iter_mut().rev() {
+        let cur = u128::from(*b) * u128::from(m) + carry;
+        *b = cur as u8;
+        carry = cur >> 8;
+    }
+    carry != 0
+}
+
+/// Convert `u64` into 32 bytes big-endian number
+pub fn from_u64(v: u64) -> [u8; 32] {
+    let mut res = [0u8; 32];
+    mul_add(&mut res, 1, v);
+    res
+}
+
+/// Multiply 32 bytes big-endian number by `u64`
+pub fn mul_u64(n: &[u8; 32], m: u64) -> Result<[u8; 32], Error> {
+    let mut res = *n;
+    if mul_add(&mut res, m, 0) {
+        return Err(Error::ExecError("Value overflow".to_string()));
+    }
+    Ok(res)
+}
+
+/// Add 32 bytes big-endian numbers
+pub fn add_u256(a: &[u8; 32], b: &[u8; 32]) -> Result<[u8; 32], Error> {
+    let mut res = [0u8; 32];
+    let mut carry = 0u16;
+    for i in (0..32).rev() {
+        let cur = u16::from(a[i]) + u16::from(b[i]) + carry;
+        res[i] = cur as u8;
+        carry = cur >> 8;
+    }
+    if carry != 0 {
+        return Err(Error::ExecError("Value overflow".to_string()));
+    }
+    Ok(res)
+}
+
+/// Convert big-endian number into decimal string
+pub fn to_decimal(n: &[u8]) -> String {
+    let mut buf = n.to_vec();
+    let mut digits = vec![];
+    loop {
+        digits.push(b'0' + div_rem(&mut buf, 10) as u8);
+        if buf.iter().all(|b| *b == 0) {
+            break;
+        }
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("Expect ASCII digits")
+}
+
+/// Format big-endian amount with selected number of decimals
+///
+/// # Arguments:
+///
+/// * n - amount in the smallest units
+/// * decimals - number of decimals for the unit
+///
+pub fn format_units(n: &[u8], decimals: usize) -> String {
+    let mut s = to_decimal(n);
+    if s.len() <= decimals {
+        s = format!("{}{}", "0".repeat(decimals - s.len() + 1), s);
+    }
+
+    let (int, frac) = s.split_at(s.len() - decimals);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        int.to_string()
+    } else {
+        format!("{}.{}", int, frac)
+    }
+}
+
+/// Parse decimal amount (ex.: `1.5`) into 32 bytes big-endian smallest units
+///
+/// # Arguments:
+///
+/// * s - decimal amount
+/// * decimals - number of decimals for the unit
+///
+pub fn parse_units(s: &str, decimals: usize) -> Result<[u8; 32], Error> {
+    let invalid = || Error::ExecError(format!("Invalid amount: {}", s));
+
+    let mut parts = s.trim().splitn(2, '.');
+    let int = parts.next().unwrap_or_default();
+    let frac = parts.next().unwrap_or_default();
+    if (int.is_empty() && frac.is_empty()) || frac.len() > decimals {
+        return Err(invalid());
+    }
+
+    let mut res = [0u8; 32];
+    let digits = int
+        .chars()
+        .chain(frac.chars())
+        .chain("0".repeat(decimals - frac.len()).chars());
+    for c in digits {
+        let d = c.to_digit(10).ok_or_else(invalid)?;
+        if mul_add(&mut res, 10, u64::from(d)) {
+            return Err(invalid());
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_to_decimal() {
+        assert_eq!(to_decimal(&[0u8; 32]), "0");
+        assert_eq!(to_decimal(&from_u64(1_000_000_007)), "1000000007");
+        assert_eq!(
+            to_decimal(&[0xff; 32]),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+        );
+    }
+
+    #[test]
+    fn should_format_units() {
+        assert_eq!(format_units(&from_u64(0), ETHER_DECIMALS), "0");
+        assert_eq!(
+            format_units(&from_u64(1_500_000_000_000_000_000), ETHER_DECIMALS),
+            "1.5"
+        );
+        assert_eq!(format_units(&from_u64(1), ETHER_DECIMALS), "0.000000000000000001");
+        assert_eq!(format_units(&from_u64(1200), 2), "12");
+    }
+
+    #[test]
+    fn should_parse_units() {
+        assert_eq!(
+            parse_units("1.5", ETHER_DECIMALS).unwrap(),
+            from_u64(1_500_000_000_000_000_000)
+        );
+        assert_eq!(parse_units("12", 2).unwrap(), from_u64(1200));
+        assert_eq!(parse_units(".01", 2).unwrap(), from_u64(1));
+        assert!(parse_units("0.001", 2).is_err());
+        assert!(parse_units("1,5", 2).is_err());
+        assert!(parse_units("", 2).is_err());
+    }
+
+    #[test]
+    fn should_multiply() {
+        assert_eq!(mul_u64(&from_u64(21_000), 1000).unwrap(), from_u64(21_000_000));
+        assert!(mul_u64(&[0xff; 32], 2).is_err());
+    }
+
+    #[test]
+    fn should_add() {
+        assert_eq!(
+            add_u256(&from_u64(0xff), &from_u64(1)).unwrap(),
+            from_u64(0x100)
+        );
+        assert_eq!(
+            add_u256(&from_u64(u64::max_value()), &from_u64(1)).unwrap()[23],
+            1
+        );
+        assert!(add_u256(&[0xff; 32], &from_u64(1)).is_err());
+    }
+}
@@ -4,8 +4,12 @@
 #![cfg_attr(feature = "dev", feature(plugin))]
 #![cfg_attr(feature = "dev", plugin(clippy))]
 
+extern crate atty;
+extern crate base64;
+extern crate chrono;
 extern crate emerald_rs as emerald;
 extern crate env_logger;
+extern crate fs2;
 extern crate hex;
 extern crate hyper;
 extern crate jsonrpc_core;
@@ -19,11 +23,18 @@ extern crate rpassword;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
+extern crate toml;
+extern crate tungstenite;
 extern crate url;
 #[macro_use]
 extern crate clap;
 
+#[cfg(test)]
+extern crate tempdir;
+
+mod audit;
 mod cmd;
 mod indicator;
 mod rpc;
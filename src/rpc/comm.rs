@@ -1,14 +1,35 @@
-//! # Send JSON encoded HTTP requests
+//! # Send JSON RPC requests to remote nodes
 
+use super::transport::{self, Transport};
 use cmd::Error;
 use jsonrpc_core::Params;
-use reqwest::{Client, Url};
-use serde_json::Value;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Proxy, Url};
+use serde_json::{self, Value};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Default connect timeout, in seconds
+pub const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+
+/// Default timeout for whole request, in seconds
+pub const DEFAULT_TIMEOUT: u64 = 30;
+
+/// Default number of retries for idempotent requests
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// Delay before first retry, doubled for each next one
+const RETRY_DELAY_MS: u64 = 500;
+
+/// JSON RPC error code for method unknown to remote node
+pub const METHOD_NOT_FOUND: i64 = -32601;
 
 lazy_static! {
-    static ref CLIENT: Client = Client::builder()
-        .build()
-        .expect("Expect to create an HTTP client");
+    /// Index of upstream to start next request with
+    static ref NEXT_UPSTREAM: AtomicUsize = AtomicUsize::new(0);
 }
 
 /// RPC methods
@@ -32,22 +53,342 @@ pub enum ClientMethod {
     /// [eth_getBalance](
     /// https://github.com/ethereumproject/go-ethereum/wiki/JSON-RPC#eth_getbalance)
     EthGetBalance,
+
+    /// [eth_call](https://github.com/ethereum/wiki/wiki/JSON-RPC#eth_call)
+    EthCall,
+
+    /// [eth_chainId](https://github.com/ethereum/EIPs/blob/master/EIPS/eip-695.md)
+    EthChainId,
+
+    /// [eth_getTransactionByHash](
+    /// https://github.com/ethereum/wiki/wiki/JSON-RPC#eth_gettransactionbyhash)
+    EthGetTxByHash,
+
+    /// [eth_getTransactionReceipt](
+    /// https://github.com/ethereum/wiki/wiki/JSON-RPC#eth_gettransactionreceipt)
+    EthGetTxReceipt,
+
+    /// [eth_blockNumber](https://github.com/ethereum/wiki/wiki/JSON-RPC#eth_blocknumber)
+    EthBlockNumber,
+
+    /// [eth_getBlockByNumber](
+    /// https://github.com/ethereum/wiki/wiki/JSON-RPC#eth_getblockbynumber)
+    EthGetBlockByNumber,
+
+    /// [eth_feeHistory](https://github.com/ethereum/execution-apis)
+    EthFeeHistory,
+
+    /// [eth_createAccessList](https://github.com/ethereum/execution-apis)
+    EthCreateAccessList,
+
+    /// [eth_getCode](https://github.com/ethereum/wiki/wiki/JSON-RPC#eth_getcode)
+    EthGetCode,
+}
+
+impl ClientMethod {
+    /// Whether request can be safely repeated
+    pub fn is_idempotent(&self) -> bool {
+        match *self {
+            ClientMethod::EthSendRawTransaction => false,
+            _ => true,
+        }
+    }
 }
 
 /// RPC method's parameters
 #[derive(Clone, Debug, PartialEq)]
 pub struct MethodParams<'a>(pub ClientMethod, pub &'a Params);
 
+/// Client settings. `timeout` is applied to all transports,
+/// `connect_timeout` and `proxy` only to HTTP(S)
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpOptions {
+    pub connect_timeout: Duration,
+    pub timeout: Duration,
+    pub retries: u32,
+    /// HTTP(S) or SOCKS5 proxy URL
+    pub proxy: Option<String>,
+    /// Custom headers, sent with each request
+    pub headers: Vec<(String, String)>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT),
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT),
+            retries: DEFAULT_RETRIES,
+            proxy: None,
+            headers: vec![],
+        }
+    }
+}
+
+/// Connector to one or more remote nodes
 pub struct RpcConnector {
-    pub url: Url,
+    pub urls: Vec<Url>,
+    /// Read requests must be answered by majority of upstreams
+    pub quorum: bool,
+    transports: Vec<Rc<Transport>>,
+    retries: u32,
 }
 
 impl RpcConnector {
-    /// Send and JSON RPC HTTP post request
+    /// Create connector for upstreams
+    ///
+    /// # Arguments:
+    ///
+    /// * urls - upstream URLs, may contain basic auth credentials
+    /// * quorum - use quorum reads
+    /// * opts - HTTP client settings
+    ///
+    pub fn new(urls: Vec<Url>, quorum: bool, opts: &HttpOptions) -> Result<RpcConnector, Error> {
+        let mut headers = HeaderMap::new();
+        for &(ref name, ref value) in &opts.headers {
+            let invalid = |e: String| Error::ExecError(format!("Invalid header `{}`: {}", name, e));
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(e.to_string()))?,
+                HeaderValue::from_str(value).map_err(|e| invalid(e.to_string()))?,
+            );
+        }
+
+        let mut builder = Client::builder()
+            .connect_timeout(opts.connect_timeout)
+            .timeout(opts.timeout)
+            .default_headers(headers);
+        if let Some(ref p) = opts.proxy {
+            builder = builder.proxy(Proxy::all(p.as_str())?);
+        }
+
+        let client = builder.build()?;
+        let mut transports = vec![];
+        for url in &urls {
+            transports.push(transport::create(url, &client, opts)?);
+        }
+
+        Ok(RpcConnector {
+            urls,
+            quorum,
+            transports,
+            retries: opts.retries,
+        })
+    }
+
+    /// Connector with same settings for single upstream,
+    /// `url` must be one of connector's upstreams
+    pub fn for_url(&self, url: &Url) -> RpcConnector {
+        let i = self
+            .urls
+            .iter()
+            .position(|u| u == url)
+            .expect("Expect known upstream");
+
+        RpcConnector {
+            urls: vec![url.clone()],
+            quorum: false,
+            transports: vec![self.transports[i].clone()],
+            retries: self.retries,
+        }
+    }
+
+    /// Wait for next subscription notification from the first upstream
+    pub fn next_notification(&self) -> Result<Value, Error> {
+        match self.transports.first() {
+            Some(t) => t.next_notification(),
+            None => Err(Error::ExecError("No upstream specified".to_string())),
+        }
+    }
+
+    /// Send JSON RPC request. Upstreams are used in round-robin
+    /// order, on failure request is repeated with the next one.
+    /// Error object of response is returned as `Error::RpcError`
     pub fn send_post(&self, params: &MethodParams) -> Result<Value, Error> {
-        let mut res = CLIENT.post(self.url.clone()).json(params).send()?;
-        let json: Value = res.json()?;
+        let request = serde_json::to_value(params)?;
+        let json = self.send_request(&request, params.0.is_idempotent())?;
+
+        response_result(&json)
+    }
+
+    /// Send requests in one JSON RPC batch
+    ///
+    /// # Return:
+    ///
+    /// * Vec<Value> - results in order of requests
+    ///
+    pub fn send_batch(&self, params: &[MethodParams]) -> Result<Vec<Value>, Error> {
+        if params.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut requests = vec![];
+        for p in params {
+            requests.push(serde_json::to_value(p)?);
+        }
+        let idempotent = params.iter().all(|p| p.0.is_idempotent());
+        let json = self.send_request(&Value::Array(requests.clone()), idempotent)?;
+
+        match json {
+            Value::Array(responses) => match_responses(&requests, &responses),
+            _ => Err(Error::ExecError(
+                "Upstream doesn't support batch requests".to_string(),
+            )),
+        }
+    }
+
+    /// Send JSON RPC request to all upstreams
+    pub fn send_post_all(
+        &self,
+        params: &MethodParams,
+    ) -> Result<Vec<(Url, Result<Value, Error>)>, Error> {
+        let request = serde_json::to_value(params)?;
+
+        Ok(self
+            .urls
+            .iter()
+            .enumerate()
+            .map(|(idx, url)| {
+                let res = self
+                    .post(idx, &request, params.0.is_idempotent())
+                    .and_then(|json| response_result(&json));
+                (hide_password(url), res)
+            })
+            .collect())
+    }
+
+    /// Send request with failover to the next upstream
+    fn send_request(&self, request: &Value, idempotent: bool) -> Result<Value, Error> {
+        let start = NEXT_UPSTREAM.fetch_add(1, Ordering::SeqCst);
+        let mut last_err = Error::ExecError("No upstream specified".to_string());
+
+        for i in 0..self.urls.len() {
+            let idx = (start + i) % self.urls.len();
+            match self.post(idx, request, idempotent) {
+                Ok(v) => return Ok(v),
+                Err(e) => {
+                    warn!("Upstream {} failed: {}", hide_password(&self.urls[idx]), e);
+                    last_err = e;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    /// Send request to single upstream, idempotent requests
+    /// are retried with exponential backoff
+    fn post(&self, idx: usize, request: &Value, idempotent: bool) -> Result<Value, Error> {
+        let retries = if idempotent { self.retries } else { 0 };
+
+        let mut attempt = 0;
+        loop {
+            match self.transports[idx].send(request) {
+                Err(ref e) if attempt < retries => {
+                    let delay = RETRY_DELAY_MS << attempt;
+                    debug!(
+                        "Request to {} failed: {}, retry in {} ms",
+                        hide_password(&self.urls[idx]),
+                        e,
+                        delay
+                    );
+                    thread::sleep(Duration::from_millis(delay));
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Result of JSON RPC response, or its error object as `Error::RpcError`
+fn response_result(json: &Value) -> Result<Value, Error> {
+    match json.get("error") {
+        Some(e) if !e.is_null() => Err(Error::RpcError(
+            e["code"].as_i64().unwrap_or(0),
+            e["message"].as_str().unwrap_or("unknown error").to_string(),
+        )),
+        _ => Ok(json["result"].clone()),
+    }
+}
+
+/// Match batch responses with requests by `id`
+///
+/// # Arguments:
+///
+/// * requests - batch requests
+/// * responses - batch responses, in any order
+///
+fn match_responses(requests: &[Value], responses: &[Value]) -> Result<Vec<Value>, Error> {
+    requests
+        .iter()
+        .map(|req| {
+            responses
+                .iter()
+                .find(|res| res["id"] == req["id"])
+                .ok_or_else(|| Error::ExecError(format!("No response for request {}", req["id"])))
+                .and_then(response_result)
+        })
+        .collect()
+}
+
+impl fmt::Display for RpcConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let urls: Vec<String> = self.urls.iter().map(|u| hide_password(u).to_string()).collect();
+        write!(f, "{}", urls.join(", "))
+    }
+}
+
+/// URL with password replaced, safe to be printed
+pub fn hide_password(url: &Url) -> Url {
+    let mut url = url.clone();
+    if url.password().is_some() {
+        let _ = url.set_password(Some("***"));
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_match_batch_responses_by_id() {
+        let requests = vec![
+            json!({"jsonrpc": "2.0", "id": 1, "method": "eth_gasPrice"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "eth_chainId"}),
+        ];
+        let responses = vec![
+            json!({"jsonrpc": "2.0", "id": 2, "result": "0x3d"}),
+            json!({"jsonrpc": "2.0", "id": 1, "result": "0x4a817c800"}),
+        ];
+
+        assert_eq!(
+            match_responses(&requests, &responses).unwrap(),
+            vec![json!("0x4a817c800"), json!("0x3d")]
+        );
+        assert!(match_responses(&requests, &responses[..1]).is_err());
+    }
+
+    #[test]
+    fn should_return_rpc_errors() {
+        assert_eq!(
+            response_result(&json!({"jsonrpc": "2.0", "id": 1, "result": null})).unwrap(),
+            Value::Null
+        );
+
+        let res = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": {"code": -32601, "message": "the method eth_chainId does not exist"}
+        });
+        match response_result(&res) {
+            Err(Error::RpcError(code, msg)) => {
+                assert_eq!(code, METHOD_NOT_FOUND);
+                assert!(msg.contains("eth_chainId"));
+            }
+            r => panic!("Expected RPC error, got {:?}", r),
+        }
 
-        Ok(json["result"].clone())
+        let requests = vec![json!({"jsonrpc": "2.0", "id": 1, "method": "eth_chainId"})];
+        assert!(match_responses(&requests, &[res]).is_err());
     }
 }
@@ -2,27 +2,37 @@
 
 mod comm;
 mod serialize;
+mod transport;
 
-pub use self::comm::{ClientMethod, MethodParams, RpcConnector};
+pub use self::comm::{
+    hide_password, ClientMethod, HttpOptions, MethodParams, RpcConnector, DEFAULT_CONNECT_TIMEOUT,
+    DEFAULT_RETRIES, DEFAULT_TIMEOUT, METHOD_NOT_FOUND,
+};
 use cmd::Error;
-use emerald::Address;
-use hex::ToHex;
+use emerald::{trim_hex, Address, Transaction};
+use hex::{FromHex, ToHex};
 use jsonrpc_core::{Params, Value};
+use serde_json::Map;
 
 /// Get nonce for address from remote node
 ///
 /// # Arguments:
 ///
 /// * addr - target address
+/// * block - block number or tag
 ///
-pub fn request_nonce(rpc: &RpcConnector, addr: &Address) -> Result<String, Error> {
-    let data = vec![
-        Value::String(addr.to_string()),
-        Value::String("latest".to_string()),
-    ];
-    let params = Params::Array(data);
-    let val = rpc.send_post(&MethodParams(ClientMethod::EthGetTxCount, &params))?;
+pub fn request_nonce(rpc: &RpcConnector, addr: &Address, block: &str) -> Result<String, Error> {
+    let params = nonce_params(addr, block);
+    let method = MethodParams(ClientMethod::EthGetTxCount, &params);
+    if rpc.quorum {
+        let mut max = 0;
+        for s in quorum_results(rpc, &method)? {
+            max = max.max(u64::from_str_radix(trim_hex(&s), 16)?);
+        }
+        return Ok(format!("0x{:x}", max));
+    }
 
+    let val = rpc.send_post(&method)?;
     match val.as_str() {
         Some(s) => Ok(s.to_string()),
         None => Err(Error::ExecError("Can't parse tx count".to_string())),
@@ -35,43 +45,340 @@ pub fn request_nonce(rpc: &RpcConnector, addr: &Address) -> Result<String, Error
 ///
 /// * rpc -
 /// * addr - target account
+/// * block - block number or tag
 ///
 /// # Return:
 ///
-/// * String - latest balance
+/// * String - balance at block
+///
+pub fn request_balance(rpc: &RpcConnector, addr: &Address, block: &str) -> Result<String, Error> {
+    let params = balance_params(addr, block);
+    let method = MethodParams(ClientMethod::EthGetBalance, &params);
+    if rpc.quorum {
+        let results = quorum_results(rpc, &method)?;
+        let first = normalize_hex(&results[0]);
+        if results.iter().any(|r| normalize_hex(r) != first) {
+            return Err(Error::ExecError(format!(
+                "Upstreams disagree on balance for {}: {}",
+                addr,
+                results.join(", ")
+            )));
+        }
+        return Ok(results[0].clone());
+    }
+
+    rpc.send_post(&method).and_then(|v| match v.as_str() {
+        Some(str) => Ok(str.to_string()),
+        None => Err(Error::ExecError(format!("Can't get balance for {}", addr))),
+    })
+}
+
+/// Balance and nonce of account
+#[derive(Debug)]
+pub struct AccountState {
+    pub balance: String,
+    pub nonce: String,
+}
+
+/// Get balances and nonces for accounts in one batch
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * addrs - target accounts
+/// * block - block number or tag
+///
+/// # Return:
+///
+/// * Vec<AccountState> - states in order of `addrs`
+///
+pub fn request_accounts(
+    rpc: &RpcConnector,
+    addrs: &[Address],
+    block: &str,
+) -> Result<Vec<AccountState>, Error> {
+    // quorum reads are not batched
+    if rpc.quorum {
+        return addrs
+            .iter()
+            .map(|a| {
+                Ok(AccountState {
+                    balance: request_balance(rpc, a, block)?,
+                    nonce: request_nonce(rpc, a, block)?,
+                })
+            })
+            .collect();
+    }
+
+    let params: Vec<(Params, Params)> = addrs
+        .iter()
+        .map(|a| (balance_params(a, block), nonce_params(a, block)))
+        .collect();
+    let mut methods = vec![];
+    for &(ref balance, ref nonce) in &params {
+        methods.push(MethodParams(ClientMethod::EthGetBalance, balance));
+        methods.push(MethodParams(ClientMethod::EthGetTxCount, nonce));
+    }
+
+    let results = rpc.send_batch(&methods)?;
+    addrs
+        .iter()
+        .zip(results.chunks(2))
+        .map(|(addr, res)| match (res[0].as_str(), res[1].as_str()) {
+            (Some(balance), Some(nonce)) => Ok(AccountState {
+                balance: balance.to_string(),
+                nonce: nonce.to_string(),
+            }),
+            _ => Err(Error::ExecError(format!("Can't get balance for {}", addr))),
+        })
+        .collect()
+}
+
+/// Values requested from remote node to fill transaction
+#[derive(Debug, Default)]
+pub struct TxParams {
+    pub nonce: Option<String>,
+    pub gas_price: Option<String>,
+    pub gas: Option<String>,
+}
+
+/// Values to be requested by `request_tx_params`
+#[derive(Debug, Default)]
+pub struct TxRequired {
+    pub nonce: bool,
+    pub gas_price: bool,
+    pub gas: bool,
+}
+
+/// Request nonce, gas price and gas estimation for transaction in one batch.
+/// Only values marked as required are requested, nonce is taken at `pending` block
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * from - sender address
+/// * tr - transaction to be estimated, `gas_limit` is ignored
+/// * required - `true` for values to be requested
 ///
-pub fn request_balance(rpc: &RpcConnector, addr: &Address) -> Result<String, Error> {
-    let data = vec![
+pub fn request_tx_params(
+    rpc: &RpcConnector,
+    from: &Address,
+    tr: &Transaction,
+    required: &TxRequired,
+) -> Result<TxParams, Error> {
+    // pending transactions from mempool are counted,
+    // so consecutive transactions don't reuse nonce
+    let nonce_params = nonce_params(from, "pending");
+    let no_params = Params::Array(vec![]);
+    let gas_params = estimate_params(from, tr);
+
+    // quorum reads are not batched
+    let batch_nonce = required.nonce && !rpc.quorum;
+    let mut methods = vec![];
+    if batch_nonce {
+        methods.push(MethodParams(ClientMethod::EthGetTxCount, &nonce_params));
+    }
+    if required.gas_price {
+        methods.push(MethodParams(ClientMethod::EthGasPrice, &no_params));
+    }
+    if required.gas {
+        methods.push(MethodParams(ClientMethod::EthEstimateGas, &gas_params));
+    }
+
+    let mut results = rpc.send_batch(&methods)?.into_iter();
+    let mut next = |required: bool, what: &str| -> Result<Option<String>, Error> {
+        if !required {
+            return Ok(None);
+        }
+        match results.next().as_ref().and_then(|v| v.as_str()) {
+            Some(s) => Ok(Some(s.to_string())),
+            None => Err(Error::ExecError(format!("Can't get {}", what))),
+        }
+    };
+
+    let mut res = TxParams {
+        nonce: next(batch_nonce, "tx count")?,
+        gas_price: next(required.gas_price, "gas price")?,
+        gas: next(required.gas, "gas estimation")?,
+    };
+    if required.nonce && !batch_nonce {
+        res.nonce = Some(request_nonce(rpc, from, "pending")?);
+    }
+
+    Ok(res)
+}
+
+/// Parameters for `eth_getBalance`
+fn balance_params(addr: &Address, block: &str) -> Params {
+    Params::Array(vec![
         Value::String(addr.to_string()),
-        Value::String("latest".to_string()),
-    ];
+        block_param(block),
+    ])
+}
 
+/// Parameters for `eth_getTransactionCount`
+fn nonce_params(addr: &Address, block: &str) -> Params {
+    Params::Array(vec![
+        Value::String(addr.to_string()),
+        block_param(block),
+    ])
+}
+
+/// Block parameter, block hash is passed as object (EIP-1898)
+fn block_param(block: &str) -> Value {
+    if block.starts_with("0x") && block.len() == 66 {
+        let mut obj = Map::new();
+        obj.insert("blockHash".to_string(), Value::String(block.to_string()));
+        Value::Object(obj)
+    } else {
+        Value::String(block.to_string())
+    }
+}
+
+/// Parameters for `eth_estimateGas`
+fn estimate_params(from: &Address, tr: &Transaction) -> Params {
+    Params::Array(vec![call_object(from, tr)])
+}
+
+/// Call object of transaction, without gas fields
+fn call_object(from: &Address, tr: &Transaction) -> Value {
+    let mut call = Map::new();
+    call.insert("from".to_string(), Value::String(from.to_string()));
+    if let Some(ref to) = tr.to {
+        call.insert("to".to_string(), Value::String(to.to_string()));
+    }
+    call.insert("value".to_string(), Value::String(quantity(&tr.value)));
+    if !tr.data.is_empty() {
+        call.insert(
+            "data".to_string(),
+            Value::String(format!("0x{}", tr.data.to_hex())),
+        );
+    }
+
+    Value::Object(call)
+}
+
+/// Send signed raw transaction to all upstreams
+///
+/// # Arguments:
+///
+/// * raw - signed tx
+///
+/// # Return:
+///
+/// * String - transaction hash, returned by any of upstreams
+///
+pub fn send_transaction(rpc: &RpcConnector, raw: &[u8]) -> Result<String, Error> {
+    let data = vec![Value::String(format!("0x{}", &raw.to_hex()))];
     let params = Params::Array(data);
-    rpc.send_post(&MethodParams(ClientMethod::EthGetBalance, &params))
-        .and_then(|v| match v.as_str() {
+
+    let results = rpc.send_post_all(&MethodParams(ClientMethod::EthSendRawTransaction, &params))?;
+
+    let mut tx_hash = None;
+    let mut last_err = Error::ExecError("No upstream specified".to_string());
+    for (url, res) in results {
+        match res.and_then(|v| match v.as_str() {
             Some(str) => Ok(str.to_string()),
-            None => Err(Error::ExecError(format!("Can't get balance for {}", addr))),
-        })
+            None => Err(Error::ExecError("Can't parse tx hash".to_string())),
+        }) {
+            Ok(hash) => {
+                info!("Transaction sent through {}", url);
+                tx_hash = Some(hash);
+            }
+            Err(e) => {
+                warn!("Can't send transaction through {}: {}", url, e);
+                last_err = e;
+            }
+        }
+    }
+
+    tx_hash.ok_or(last_err)
 }
 
-/// Get estimated gas from remote node
+/// Execute message call without creating a transaction
 ///
 /// # Arguments:
 ///
 /// * rpc -
+/// * to - contract address
+/// * data - call data
 ///
-pub fn request_gas(rpc: &RpcConnector) -> Result<String, Error> {
-    let data = vec![Value::String("latest".to_string())];
-    let params = Params::Array(data);
-    let val = rpc.send_post(&MethodParams(ClientMethod::EthEstimateGas, &params))?;
+/// # Return:
+///
+/// * Vec<u8> - returned data
+///
+pub fn call(rpc: &RpcConnector, to: &Address, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut call = Map::new();
+    call.insert("to".to_string(), Value::String(to.to_string()));
+    call.insert("data".to_string(), Value::String(format!("0x{}", data.to_hex())));
+
+    let params = Params::Array(vec![Value::Object(call), Value::String("latest".to_string())]);
+    let val = rpc.send_post(&MethodParams(ClientMethod::EthCall, &params))?;
 
     match val.as_str() {
-        Some(s) => Ok(s.to_string()),
-        None => Err(Error::ExecError("Can't estimate required gas".to_string())),
+        Some(s) => Vec::from_hex(trim_hex(s)).map_err(Error::from),
+        None => Err(Error::ExecError(format!("Can't call contract {}", to))),
     }
 }
 
-/// Get gas price from remote node
+/// Get chain id of remote node, `None` if node answers that `eth_chainId`
+/// method doesn't exist. Any other error or empty result is an error.
+/// `net_version` isn't used as fallback, network id differs from chain id
+/// for some chains, ex.: ETC mainnet has network id 1 and chain id 61
+///
+/// # Arguments:
+///
+/// * rpc -
+///
+pub fn request_chain_id(rpc: &RpcConnector) -> Result<Option<u64>, Error> {
+    let params = Params::Array(vec![]);
+    let val = match rpc.send_post(&MethodParams(ClientMethod::EthChainId, &params)) {
+        Ok(v) => v,
+        Err(Error::RpcError(METHOD_NOT_FOUND, _)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    match val.as_str() {
+        Some(s) => Ok(Some(u64::from_str_radix(trim_hex(s), 16)?)),
+        None => Err(Error::ExecError(format!("Can't parse chain id: {}", val))),
+    }
+}
+
+/// Get transaction by hash, `None` if transaction is unknown to remote node
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * hash - transaction hash
+///
+pub fn request_transaction(rpc: &RpcConnector, hash: &str) -> Result<Option<Value>, Error> {
+    let params = Params::Array(vec![Value::String(hash.to_string())]);
+    let val = rpc.send_post(&MethodParams(ClientMethod::EthGetTxByHash, &params))?;
+
+    match val {
+        Value::Null => Ok(None),
+        v => Ok(Some(v)),
+    }
+}
+
+/// Get receipt of mined transaction, `None` if transaction is not mined yet
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * hash - transaction hash
+///
+pub fn request_receipt(rpc: &RpcConnector, hash: &str) -> Result<Option<Value>, Error> {
+    let params = Params::Array(vec![Value::String(hash.to_string())]);
+    let val = rpc.send_post(&MethodParams(ClientMethod::EthGetTxReceipt, &params))?;
+
+    match val {
+        Value::Null => Ok(None),
+        v => Ok(Some(v)),
+    }
+}
+
+/// Get gas price suggested by remote node
 ///
 /// # Arguments:
 ///
@@ -83,26 +390,203 @@ pub fn request_gas_price(rpc: &RpcConnector) -> Result<String, Error> {
 
     match val.as_str() {
         Some(s) => Ok(s.to_string()),
-        None => Err(Error::ExecError("Can't estimate gas price".to_string())),
+        None => Err(Error::ExecError("Can't get gas price".to_string())),
     }
 }
 
-/// Send signed raw transaction to the remote client
+/// Get number of the latest block
 ///
 /// # Arguments:
 ///
-/// * raw - signed tx
+/// * rpc -
 ///
-/// # Return:
+pub fn request_block_number(rpc: &RpcConnector) -> Result<u64, Error> {
+    let params = Params::Array(vec![]);
+    let val = rpc.send_post(&MethodParams(ClientMethod::EthBlockNumber, &params))?;
+
+    match val.as_str() {
+        Some(s) => u64::from_str_radix(trim_hex(s), 16).map_err(Error::from),
+        None => Err(Error::ExecError("Can't get block number".to_string())),
+    }
+}
+
+/// Get blocks with full transactions in one batch.
+/// Blocks unknown to remote node are skipped
 ///
-/// * String - transaction hash
+/// # Arguments:
 ///
-pub fn send_transaction(rpc: &RpcConnector, raw: &[u8]) -> Result<String, Error> {
-    let data = vec![Value::String(format!("0x{}", &raw.to_hex()))];
-    let params = Params::Array(data);
-    rpc.send_post(&MethodParams(ClientMethod::EthSendRawTransaction, &params))
-        .and_then(|v| match v.as_str() {
-            Some(str) => Ok(str.to_string()),
-            None => Err(Error::ExecError("Can't parse tx hash".to_string())),
+/// * rpc -
+/// * numbers - block numbers
+///
+pub fn request_blocks(rpc: &RpcConnector, numbers: &[u64]) -> Result<Vec<Value>, Error> {
+    let params: Vec<Params> = numbers
+        .iter()
+        .map(|n| Params::Array(vec![Value::String(format!("0x{:x}", n)), Value::Bool(true)]))
+        .collect();
+    let methods: Vec<MethodParams> = params
+        .iter()
+        .map(|p| MethodParams(ClientMethod::EthGetBlockByNumber, p))
+        .collect();
+
+    Ok(rpc
+        .send_batch(&methods)?
+        .into_iter()
+        .filter(|b| !b.is_null())
+        .collect())
+}
+
+/// Get base fees and priority fees of recent blocks (EIP-1559)
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * blocks - number of blocks, up to the latest one
+/// * percentile - percentile of priority fees within each block
+///
+pub fn request_fee_history(
+    rpc: &RpcConnector,
+    blocks: u64,
+    percentile: usize,
+) -> Result<Value, Error> {
+    let params = Params::Array(vec![
+        Value::String(format!("0x{:x}", blocks)),
+        Value::String("latest".to_string()),
+        Value::Array(vec![Value::from(percentile)]),
+    ]);
+    let val = rpc.send_post(&MethodParams(ClientMethod::EthFeeHistory, &params))?;
+
+    match val {
+        Value::Object(_) => Ok(val),
+        _ => Err(Error::ExecError(
+            "Upstream doesn't support fee history (EIP-1559)".to_string(),
+        )),
+    }
+}
+
+/// Get access list of addresses and storage keys accessed by transaction (EIP-2930)
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * from - sender address
+/// * tr - transaction, gas fields are ignored
+///
+pub fn request_access_list(
+    rpc: &RpcConnector,
+    from: &Address,
+    tr: &Transaction,
+) -> Result<Value, Error> {
+    let params = Params::Array(vec![
+        call_object(from, tr),
+        Value::String("pending".to_string()),
+    ]);
+    let val = rpc.send_post(&MethodParams(ClientMethod::EthCreateAccessList, &params))?;
+
+    if let Some(e) = val["error"].as_str() {
+        return Err(Error::ExecError(format!("Can't create access list: {}", e)));
+    }
+    match val["accessList"] {
+        Value::Array(_) => Ok(val["accessList"].clone()),
+        _ => Err(Error::ExecError(
+            "Upstream doesn't support access lists (EIP-2930)".to_string(),
+        )),
+    }
+}
+
+/// Get code deployed at address
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * addr - contract address
+/// * block - block number or tag
+///
+pub fn request_code(rpc: &RpcConnector, addr: &Address, block: &str) -> Result<Vec<u8>, Error> {
+    let params = Params::Array(vec![Value::String(addr.to_string()), block_param(block)]);
+    let val = rpc.send_post(&MethodParams(ClientMethod::EthGetCode, &params))?;
+
+    match val.as_str() {
+        Some(s) => Vec::from_hex(trim_hex(s)).map_err(Error::from),
+        None => Err(Error::ExecError("Can't get contract code".to_string())),
+    }
+}
+
+/// Send request to all upstreams, majority of them must respond
+///
+/// # Arguments:
+///
+/// * rpc -
+/// * method - request
+///
+fn quorum_results(rpc: &RpcConnector, method: &MethodParams) -> Result<Vec<String>, Error> {
+    let results: Vec<String> = rpc
+        .send_post_all(method)?
+        .into_iter()
+        .filter_map(|(url, res)| match res {
+            Ok(Value::String(s)) => Some(s),
+            Ok(v) => {
+                warn!("Unexpected response from {}: {}", url, v);
+                None
+            }
+            Err(e) => {
+                warn!("Upstream {} failed: {}", url, e);
+                None
+            }
         })
+        .collect();
+
+    if results.is_empty() || results.len() * 2 <= rpc.urls.len() {
+        return Err(Error::ExecError(format!(
+            "No quorum: {} of {} upstreams responded",
+            results.len(),
+            rpc.urls.len()
+        )));
+    }
+
+    Ok(results)
+}
+
+/// Hex number without prefix and leading zeros
+fn normalize_hex(s: &str) -> String {
+    trim_hex(s).trim_start_matches('0').to_lowercase()
+}
+
+/// JSON-RPC quantity, nodes reject hex numbers with leading zeros
+fn quantity(value: &[u8]) -> String {
+    match normalize_hex(&value.to_hex()).as_str() {
+        "" => "0x0".to_string(),
+        v => format!("0x{}", v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn tx(value: [u8; 32]) -> Transaction {
+        Transaction {
+            nonce: 0,
+            gas_price: [0u8; 32],
+            gas_limit: 21000,
+            to: Some(Address::from_str("0x3535353535353535353535353535353535353535").unwrap()),
+            value,
+            data: vec![],
+        }
+    }
+
+    #[test]
+    fn should_encode_value_as_quantity() {
+        let from = Address::from_str("0x0e7c045110b8dbf29765047380898919c5cb56f4").unwrap();
+
+        let mut value = [0u8; 32];
+        value[24..].copy_from_slice(&[0x0d, 0xe0, 0xb6, 0xb3, 0xa7, 0x64, 0x00, 0x00]);
+        let call = call_object(&from, &tx(value));
+        assert_eq!(call["value"], "0xde0b6b3a7640000");
+        assert_eq!(call["from"], "0x0e7c045110b8dbf29765047380898919c5cb56f4");
+        assert!(call.get("data").is_none());
+
+        let call = call_object(&from, &tx([0u8; 32]));
+        assert_eq!(call["value"], "0x0");
+    }
 }
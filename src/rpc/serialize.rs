@@ -25,10 +25,19 @@ impl<'a> Serialize for MethodParams<'a> {
     {
         match self.0 {
             ClientMethod::EthGasPrice => serialize("eth_gasPrice", self.1, s),
-            ClientMethod::EthEstimateGas => serialize("eth_etsimateGas", self.1, s),
+            ClientMethod::EthEstimateGas => serialize("eth_estimateGas", self.1, s),
             ClientMethod::EthGetTxCount => serialize("eth_getTransactionCount", self.1, s),
             ClientMethod::EthSendRawTransaction => serialize("eth_sendRawTransaction", self.1, s),
             ClientMethod::EthGetBalance => serialize("eth_getBalance", self.1, s),
+            ClientMethod::EthCall => serialize("eth_call", self.1, s),
+            ClientMethod::EthChainId => serialize("eth_chainId", self.1, s),
+            ClientMethod::EthGetTxByHash => serialize("eth_getTransactionByHash", self.1, s),
+            ClientMethod::EthGetTxReceipt => serialize("eth_getTransactionReceipt", self.1, s),
+            ClientMethod::EthBlockNumber => serialize("eth_blockNumber", self.1, s),
+            ClientMethod::EthGetBlockByNumber => serialize("eth_getBlockByNumber", self.1, s),
+            ClientMethod::EthFeeHistory => serialize("eth_feeHistory", self.1, s),
+            ClientMethod::EthCreateAccessList => serialize("eth_createAccessList", self.1, s),
+            ClientMethod::EthGetCode => serialize("eth_getCode", self.1, s),
         }
     }
 }